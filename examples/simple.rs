@@ -1,32 +1,41 @@
-use std::io;
+use std::{
+    io,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use web::{app, cookie::Cookie, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use web::{
+    app,
+    cookie::Cookie,
+    extract::{Json, Path},
+    Request, Response, StatusCode,
+};
 
 // TODO: This kind of works but `app` isn't strongly typed
 // #[web::main]
-// fn main(app: Runtime) {
+// fn main(app: &mut App) {
 //     app.log_with(|_| println!("special logger for home route"));
 //     app.get("/", home);
 
 //     app.log(); // Turn on default logger
 //     app.get("/a", a);
-//     app.get("/a", a2);
 //     app.get("/user/:name", user);
 //     app.post("/", post);
 // }
 
 fn main() -> io::Result<()> {
-    app::listen_on("127.0.0.1:3000", |mut app| async move {
+    app::listen_on("127.0.0.1:3000", |app| {
         app.log_with(|_| println!("special logger for home route"));
         app.get("/", home);
 
         app.log(); // Turn on default logger
         app.get("/a", a);
-        app.get("/a", a2);
         app.get("/user/:name", user);
         app.post("/", post);
 
-        app.listen().await; // TODO: Don't want this
+        app.state(AtomicUsize::new(0));
+        app.get("/visits", visits);
+        app.serve_dir("/static", "examples/static");
     })
 }
 
@@ -35,22 +44,38 @@ async fn home(_: Request, res: Response) -> Response {
 }
 
 async fn a(_: Request, res: Response) -> Response {
-    res.status(StatusCode::OK)
-        .set_cookie(Cookie::new("token", "asdfasdfasdf"))
-        .content("you will never see this".to_string())
+    res.set_cookie(Cookie::new("token", "asdfasdfasdf"))
+        .content("hi".to_string())
 }
 
-async fn a2(_: Request, res: Response) -> Response {
-    res.content("hi".to_string())
+#[derive(Deserialize)]
+struct User {
+    name: String,
 }
 
-async fn user(req: Request, res: Response) -> Response {
-    let name = req.params.get("name").unwrap();
-    res.content(format!("Hello, {}", name))
-        .status(StatusCode::OK)
+async fn user(Path(user): Path<User>) -> String {
+    format!("Hello, {}", user.name)
 }
 
-async fn post(req: Request, res: Response) -> Response {
-    res.status(StatusCode::OK)
-        .content(format!("Your username is {}", req.body["username"]))
+#[derive(Deserialize)]
+struct Login {
+    username: String,
+}
+
+async fn post(Json(login): Json<Login>) -> (StatusCode, String) {
+    (
+        StatusCode::OK,
+        format!("Your username is {}", login.username),
+    )
+}
+
+#[derive(Serialize)]
+struct Visits {
+    visits: usize,
+}
+
+async fn visits(req: Request, res: Response) -> Response {
+    let visits = req.state::<AtomicUsize>().unwrap();
+    let n = visits.fetch_add(1, Ordering::SeqCst) + 1;
+    res.json(&Visits { visits: n })
 }
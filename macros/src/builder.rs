@@ -42,7 +42,7 @@ pub fn builder(input: TokenStream) -> TokenStream {
         };
 
         quote! {
-            fn #name(mut self, #name: #param_ty) -> Self {
+            pub fn #name(mut self, #name: #param_ty) -> Self {
                 self.#name = #set;
                 self
             }
@@ -28,10 +28,7 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
     // Doesn't handle irregular use cases like preexisting return type (is there any need for this?)
     let expanded = quote! {
         fn main() -> ::std::io::Result<()> {
-            web::App::new("127.0.0.1:3000", |mut #ident| async move {
-                #block
-                #ident.listen().await;
-            })
+            web::app::listen_on("127.0.0.1:3000", |#ident| #block)
         }
     };
     TokenStream::from(expanded)
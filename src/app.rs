@@ -1,17 +1,26 @@
 use std::{
-    future::Future,
+    any::{Any, TypeId},
+    collections::HashMap,
     io,
     net::ToSocketAddrs,
-    pin::Pin,
-    sync::{Arc, Mutex},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
 };
 
+use macros::Builder;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream},
+    io::{AsyncRead, AsyncWrite, BufWriter},
+    net::TcpListener,
 };
 
-use crate::{route::Route, Request, Response};
+use crate::{
+    handler::{Handler, IntoHandler},
+    io::request::{ParseError, Version},
+    route::{Match, Route, Router},
+    static_files::ServeDir,
+    Request, Response, StatusCode,
+};
 
 #[derive(PartialEq, Clone, Debug)]
 pub enum Method {
@@ -35,10 +44,20 @@ pub enum Method {
 
     /// The PATCH method applies partial modifications to a resource.
     PATCH,
+
+    /// The HEAD method asks for a response identical to a GET request, but without the response
+    /// body.
+    HEAD,
+
+    /// The OPTIONS method describes the communication options for the target resource.
+    OPTIONS,
+
+    /// The CONNECT method establishes a tunnel to the server identified by the target resource.
+    CONNECT,
 }
 
 impl TryFrom<&str> for Method {
-    type Error = &'static str;
+    type Error = ParseError;
 
     fn try_from(s: &str) -> Result<Self, Self::Error> {
         Ok(match s {
@@ -48,105 +67,215 @@ impl TryFrom<&str> for Method {
             "DELETE" => Method::DELETE,
             "TRACE" => Method::TRACE,
             "PATCH" => Method::PATCH,
-            _ => return Err("invalid method"),
+            "HEAD" => Method::HEAD,
+            "OPTIONS" => Method::OPTIONS,
+            "CONNECT" => Method::CONNECT,
+            _ => return Err(ParseError::InvalidMethod),
         })
     }
 }
 
-type Cfg = Box<dyn Fn(Runtime) -> Pin<Box<dyn std::future::Future<Output = ()> + Send>> + Send>;
-fn make_cfg<T>(f: fn(Runtime) -> T) -> Cfg
-where
-    T: Future<Output = ()> + Send + 'static,
-{
-    Box::new(move |rt| Box::pin(f(rt)))
-}
-
-type Handler =
-    Box<dyn Fn(Request, Response) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync>;
-fn make_handler<T>(f: fn(Request, Response) -> T) -> Handler
-where
-    T: Future<Output = Response> + Send + 'static,
-{
-    Box::new(move |req, res| Box::pin(f(req, res)))
-}
-
 macro_rules! add_endpoint {
     ($name:ident, $method:path) => {
-        pub fn $name<T>(&mut self, route: impl ToString, handler: fn(Request, Response) -> T)
-        where
-            T: Future<Output = Response> + Send + 'static,
-        {
-            self.endpoint(route, make_handler(handler), $method);
+        pub fn $name<Args>(&mut self, route: impl ToString, handler: impl IntoHandler<Args>) {
+            self.endpoint(route, handler.into_handler(), $method);
         }
     };
 }
 
-// For lack of a better name
-pub struct Runtime {
-    stream: TcpStream,
-    logging: Option<Box<dyn Fn(&Request) + Send>>,
-    identified: bool,
-    logged: bool,
-    request: Request,
-    response: Pin<Box<dyn Future<Output = Response> + Send + 'static>>,
+/// Values shared by every request, looked up by their type.
+#[derive(Default)]
+pub(crate) struct State(HashMap<TypeId, Arc<dyn Any + Send + Sync>>);
+
+impl State {
+    pub(crate) fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let state = self.0.get(&TypeId::of::<T>())?.clone();
+        state.downcast().ok()
+    }
 }
 
-impl Runtime {
-    async fn run(mut stream: TcpStream, cfg: Arc<Mutex<Cfg>>) {
-        let mut buffer = [0; 1024];
-        stream.read(&mut buffer).await.unwrap();
-        let rt = Runtime {
-            stream,
-            logging: None,
-            identified: false,
-            logged: false,
-            request: Request::try_from(&buffer).unwrap(),
-            response: Box::pin(async { Response::default() }),
-        };
+/// Settings for reading requests off a connection, applied before any routes are configured.
+#[derive(Clone, Builder)]
+pub struct Config {
+    /// The largest start-line plus headers, in bytes, that will be accepted.
+    pub(crate) max_header_size: usize,
 
-        let fut = {
-            let cfg = cfg.lock().unwrap();
-            cfg(rt)
-        };
-        fut.await;
-    }
+    /// The longest request-target, in bytes, that will be accepted.
+    pub(crate) max_uri_length: usize,
+
+    /// The largest request body, in bytes, that will be accepted.
+    pub(crate) max_body_size: usize,
+
+    /// The largest single part of a `multipart/form-data` body, in bytes, that will be accepted.
+    pub(crate) max_part_size: usize,
+
+    /// The largest `multipart/form-data` body, in bytes, that will be accepted. This replaces
+    /// `max_body_size` for multipart bodies, which are never held in memory all at once.
+    pub(crate) max_multipart_size: usize,
+
+    /// The largest part of a `multipart/form-data` body, in bytes, that is kept in memory rather
+    /// than written to `upload_dir`.
+    pub(crate) max_part_memory: usize,
+
+    /// Where uploaded files too large to keep in memory are stored while their request is
+    /// handled.
+    pub(crate) upload_dir: PathBuf,
+
+    /// How long a persistent connection may sit idle waiting for its next request before it is
+    /// closed.
+    pub(crate) keep_alive_timeout: Duration,
+
+    /// How long a request may take to arrive in full once its first byte has, after which it is
+    /// answered with 408 Request Timeout.
+    pub(crate) read_timeout: Duration,
+}
 
-    pub async fn listen(&mut self) {
-        if !self.identified {
-            self.log_route(); // TODO: Can we do something special knowing it's 404?
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_header_size: 8 * 1024,
+            max_uri_length: 4 * 1024,
+            max_body_size: 2 * 1024 * 1024,
+            max_part_size: 16 * 1024 * 1024,
+            max_multipart_size: 64 * 1024 * 1024,
+            max_part_memory: 64 * 1024,
+            upload_dir: std::env::temp_dir(),
+            keep_alive_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_secs(30),
         }
+    }
+}
 
-        let res = std::mem::replace(&mut self.response, Box::pin(async { Response::default() }));
-        self.stream
-            .write(res.await.to_string().as_bytes())
-            .await
-            .unwrap();
-        self.stream.flush().await.unwrap();
+pub(crate) type Logger = Box<dyn Fn(&Request) + Send + Sync>;
+
+/// The routes and loggers of an application. It is configured once before the server starts
+/// and then shared, unchanged, by every connection.
+///
+/// Each segment of a route is matched as one of:
+///
+/// - static text, like `robots.txt` or `about-us`, which matches only itself;
+/// - a `:name` param, which matches any one segment;
+/// - a regex, for segments using regex syntax other than `.`, `-` and `_`, which has to match
+///   the whole segment;
+/// - a `*name` wildcard, last in the route, which matches the rest of the path.
+///
+/// Static segments win over params, params over regexes and regexes over wildcards.
+///
+/// **Breaking change:** routes used to treat every segment that wasn't purely alphanumeric as
+/// an unanchored regex. Now `/h+` no longer matches `/hi`, and segments such as `user_id` or
+/// `v1.0` that used to be regexes are matched literally. Write `.*h+.*` to keep matching
+/// anywhere in the segment.
+pub struct App {
+    router: Router,
+    logging: Option<Arc<Logger>>,
+    state: Arc<State>,
+
+    /// Content-types for served files, by lowercase extension, that replace the defaults.
+    mime_types: HashMap<String, String>,
+}
+
+impl App {
+    fn new() -> Self {
+        Self {
+            router: Router::default(),
+            logging: None,
+            state: Arc::default(),
+            mime_types: HashMap::new(),
+        }
     }
 
-    // Bad naming again
-    fn log_route(&mut self) {
-        if let Some(logger) = &self.logging {
-            if !self.logged {
-                logger(&self.request);
-                self.logged = true;
+    /// Serves requests off `stream` in the order they arrive until the client asks to close the
+    /// connection or it sits idle for longer than the keep-alive timeout.
+    async fn run<S>(self: Arc<Self>, stream: S, config: Arc<Config>)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        // Responses are written piece by piece, so collect them before they hit the socket
+        let mut stream = BufWriter::new(stream);
+        let mut buf = Vec::new();
+        loop {
+            let request = match Request::read(&mut stream, &mut buf, &config).await {
+                Ok(Some(request)) => request,
+                Ok(None) => return,
+                Err(e) => {
+                    let res = Response::new()
+                        .status(e.status())
+                        .content(e.to_string())
+                        .connection(false);
+                    let _ = res.write_to(&mut stream, false).await;
+                    return;
+                }
+            };
+
+            let keep_alive = request.keep_alive();
+            let chunked = request.version == Version::Http11;
+            let res = self
+                .respond(request)
+                .await
+                .with_mime_types(&self.mime_types);
+            // Without chunked encoding the end of a streamed body is marked by closing
+            let keep_alive = keep_alive && (chunked || !res.is_streamed());
+            let res = res.connection(keep_alive);
+            if res.write_to(&mut stream, chunked).await.is_err() || !keep_alive {
+                return;
             }
         }
     }
 
-    fn endpoint(&mut self, route: impl ToString, handler: Handler, method: Method) {
-        let route = Route::from(route);
-
-        if route == self.request.route && method == self.request.method {
-            self.identified = true;
-            self.log_route();
+    /// Runs the endpoint matching `request`, falling back to a 404 page if the path isn't routed
+    /// or a 405 listing the allowed methods if it is routed for other methods. HEAD requests run
+    /// the GET endpoint and OPTIONS requests list the allowed methods unless they're routed
+    /// explicitly.
+    async fn respond(&self, mut request: Request) -> Response {
+        let head = request.method == Method::HEAD;
+        request.state = self.state.clone();
+        let allowed = match self.router.find(&request.method, &request.route) {
+            Match::Found(endpoint, params) => {
+                if let Some(logger) = &endpoint.logger {
+                    logger(&request);
+                }
+                request.params = params;
+                let conditions = match request.method {
+                    Method::GET | Method::HEAD => Some(Response::conditions(&request.headers)),
+                    _ => None,
+                };
+                let mut res = endpoint.handler.call(request, Response::new()).await;
+                if let Some(conditions) = conditions {
+                    res = res.check_conditions(&conditions);
+                }
+                return if head { res.without_body() } else { res };
+            }
+            Match::MethodNotAllowed(allowed) => Some(allowed),
+            Match::NotFound => None,
+        };
 
-            let mut req = self.request.clone();
-            req.populate_params(&route);
-            let res =
-                std::mem::replace(&mut self.response, Box::pin(async { Response::default() }));
-            self.response = Box::pin(async move { (handler)(req, res.await).await });
+        // TODO: Can we do something special knowing it's 404?
+        if let Some(logger) = &self.logging {
+            logger(&request);
         }
+        let res = match allowed {
+            Some(allowed) => {
+                let allowed: Vec<_> = allowed.iter().map(|m| format!("{:?}", m)).collect();
+                let status = match request.method {
+                    Method::OPTIONS => StatusCode::NoContent,
+                    _ => StatusCode::MethodNotAllowed,
+                };
+                Response::new()
+                    .status(status)
+                    .header("Allow", allowed.join(", "))
+            }
+            None => Response::default(),
+        };
+        if head {
+            res.without_body()
+        } else {
+            res
+        }
+    }
+
+    fn endpoint(&mut self, route: impl ToString, handler: Box<dyn Handler>, method: Method) {
+        self.router
+            .add(method, Route::from(route), handler, self.logging.clone());
     }
     add_endpoint!(get, Method::GET);
     add_endpoint!(post, Method::POST);
@@ -154,32 +283,468 @@ impl Runtime {
     add_endpoint!(delete, Method::DELETE);
     add_endpoint!(trace, Method::TRACE);
     add_endpoint!(patch, Method::PATCH);
+    add_endpoint!(head, Method::HEAD);
+    add_endpoint!(options, Method::OPTIONS);
+    add_endpoint!(connect, Method::CONNECT);
 
+    /// Serves the files under `dir` at `route`, so `app.serve_dir("/assets", "./public")` answers
+    /// `GET /assets/css/site.css` with `./public/css/site.css`. See [`ServeDir`] for what is
+    /// refused.
+    pub fn serve_dir(&mut self, route: impl ToString, dir: impl Into<PathBuf>) {
+        let route = route.to_string();
+        let handler = ServeDir::new(&route, dir);
+        self.get(format!("{}/*path", route.trim_end_matches('/')), handler);
+    }
+
+    /// Serves files ending in `.extension` as `content_type` instead of the type from the built-in
+    /// table, so `app.mime_type("md", "text/plain; charset=utf-8")` shows markdown as text.
+    pub fn mime_type(&mut self, extension: impl ToString, content_type: impl ToString) {
+        let extension = extension.to_string();
+        let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+        self.mime_types.insert(extension, content_type.to_string());
+    }
+
+    /// Logs every request to endpoints registered after this is called, and to unmatched routes.
     pub fn log(&mut self) {
-        self.logging = Some(Box::new(|req| {
+        self.logging = Some(Arc::new(Box::new(|req| {
             println!("{:?}", req);
-        }));
+        })));
     }
 
     pub fn log_with(&mut self, logger: fn(&Request)) {
-        self.logging = Some(Box::new(logger));
+        self.logging = Some(Arc::new(Box::new(logger)));
+    }
+
+    /// Shares `state` with every request, where handlers can get it back with
+    /// [`Request::state`]. Only one value of each type is kept, so calling this again with the
+    /// same type replaces the last value.
+    pub fn state<T: Send + Sync + 'static>(&mut self, state: T) {
+        Arc::get_mut(&mut self.state)
+            .expect("state is only shared once the app is listening")
+            .0
+            .insert(TypeId::of::<T>(), Arc::new(state));
     }
 }
 
+pub fn listen_on<A: ToSocketAddrs>(addr: A, cfg: impl FnOnce(&mut App)) -> io::Result<()> {
+    listen_on_with(addr, Config::default(), cfg)
+}
+
+/// Configures an [`App`] with `cfg` and serves it on `addr` until an error occurs. The app is
+/// configured exactly once, before the first connection is accepted.
 #[tokio::main]
-pub async fn listen_on<A: ToSocketAddrs, T>(addr: A, cfg: fn(Runtime) -> T) -> io::Result<()>
-where
-    T: Future<Output = ()> + Send + 'static,
-{
+pub async fn listen_on_with<A: ToSocketAddrs>(
+    addr: A,
+    config: Config,
+    cfg: impl FnOnce(&mut App),
+) -> io::Result<()> {
+    let mut app = App::new();
+    cfg(&mut app);
+    let app = Arc::new(app);
+    let config = Arc::new(config);
+
     let addr = addr.to_socket_addrs()?.find(|_| true).unwrap();
     let listener = TcpListener::bind(addr).await?;
-
-    let cfg = Arc::new(Mutex::new(make_cfg(cfg)));
     loop {
         let (socket, _) = listener.accept().await?;
-        let cfg = cfg.clone();
+        tokio::spawn(app.clone().run(socket, config.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde::Deserialize;
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::{
+        extract::{Json, Path, State},
+        handler::BoxFuture,
+    };
+
+    #[derive(Deserialize)]
+    struct Rename {
+        name: String,
+    }
+
+    async fn rename(
+        Path(id): Path<HashMap<String, u32>>,
+        Json(body): Json<Rename>,
+        State(visits): State<AtomicUsize>,
+    ) -> String {
+        format!(
+            "{} is now {} after {} visits",
+            id["id"],
+            body.name,
+            visits.load(Ordering::SeqCst)
+        )
+    }
+
+    async fn hello(req: Request, res: Response) -> Response {
+        res.status(StatusCode::OK)
+            .content(format!("hello {}", req.params["name"]))
+    }
+
+    async fn count(req: Request, res: Response) -> Response {
+        let (sender, res) = res.status(StatusCode::OK).stream();
+        let n: usize = req.params["n"].parse().unwrap();
         tokio::spawn(async move {
-            Runtime::run(socket, cfg).await;
+            for i in 0..n {
+                sender.send(i.to_string()).await.unwrap();
+            }
         });
+        res
+    }
+
+    struct Greeting(&'static str);
+
+    impl Handler for Greeting {
+        fn call(&self, _: Request, res: Response) -> BoxFuture<Response> {
+            let greeting = self.0;
+            Box::pin(async move { res.content(greeting.to_string()) })
+        }
+    }
+
+    fn serve(config: Config) -> tokio::io::DuplexStream {
+        let mut app = App::new();
+        app.state(AtomicUsize::new(0));
+        app.get("/count/:n", count);
+        app.get("/:name", hello);
+        app.get("/greeting/struct", Greeting("howdy"));
+        app.put("/user/:id", rename);
+        app.serve_dir("/files/", "static");
+
+        let prefix = String::from("visit");
+        app.get("/visits/count", move |req: Request, res: Response| {
+            let prefix = prefix.clone();
+            async move {
+                let visits = req.state::<AtomicUsize>().unwrap();
+                let n = visits.fetch_add(1, Ordering::SeqCst) + 1;
+                res.content(format!("{} {}", prefix, n))
+            }
+        });
+
+        let (client, server) = duplex(4096);
+        tokio::spawn(Arc::new(app).run(server, Arc::new(config)));
+        client
+    }
+
+    #[tokio::test]
+    async fn keep_alive() {
+        let mut client = serve(Config::default());
+        client
+            .write_all(
+                b"GET /a HTTP/1.1\r\n\r\nGET /John%20Doe?x=1 HTTP/1.1\r\nconnection: close\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        let a = res.find("hello a").unwrap();
+        let b = res.find("hello John Doe").unwrap();
+        assert!(a < b);
+        assert!(res.contains("Connection: keep-alive"));
+        assert!(res.contains("Connection: close"));
+    }
+
+    #[tokio::test]
+    async fn http_10_closes() {
+        let mut client = serve(Config::default());
+        client
+            .write_all(b"GET /a HTTP/1.0\r\n\r\nGET /b HTTP/1.0\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        assert!(res.contains("hello a"));
+        assert!(!res.contains("hello b"));
+    }
+
+    #[tokio::test]
+    async fn handlers_and_state() {
+        let mut client = serve(Config::default());
+        client
+            .write_all(b"GET /visits/count HTTP/1.1\r\n\r\nGET /visits/count HTTP/1.1\r\n\r\nGET /greeting/struct HTTP/1.1\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        assert!(res.contains("visit 1"));
+        assert!(res.contains("visit 2"));
+        assert!(res.ends_with("howdy"));
+    }
+
+    #[tokio::test]
+    async fn extractors() {
+        let body = r#"{"name": "bob"}"#;
+        let mut client = serve(Config::default());
+        client
+            .write_all(
+                format!(
+                    "PUT /user/3 HTTP/1.1\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        client
+            .write_all(b"PUT /user/x HTTP/1.1\r\ncontent-type: application/json\r\ncontent-length: 2\r\n\r\n{}")
+            .await
+            .unwrap();
+        client
+            .write_all(b"PUT /user/3 HTTP/1.1\r\ncontent-type: application/json\r\ncontent-length: 2\r\n\r\n{}")
+            .await
+            .unwrap();
+        client
+            .write_all(b"PUT /user/3 HTTP/1.1\r\ncontent-type: application/json\r\ncontent-length: 9\r\nconnection: close\r\n\r\n{\"name\": ")
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        let mut responses = res.split("HTTP/1.1 ").skip(1);
+        assert!(responses
+            .next()
+            .unwrap()
+            .ends_with("3 is now bob after 0 visits"));
+        assert!(responses.next().unwrap().starts_with("400 Bad Request"));
+        let unprocessable = responses.next().unwrap();
+        assert!(unprocessable.starts_with("422 Unprocessable Entity"));
+        assert!(unprocessable.ends_with(r#"{"error":"missing field `name` at line 1 column 2"}"#));
+        let malformed = responses.next().unwrap();
+        assert!(malformed.starts_with("422 Unprocessable Entity"));
+        assert!(malformed.ends_with(r#"{"error":"EOF while parsing a value at line 1 column 9"}"#));
+    }
+
+    #[tokio::test]
+    async fn expect_continue() {
+        let mut client = serve(Config::default());
+        client
+            .write_all(b"PUT /user/3 HTTP/1.1\r\ncontent-type: application/json\r\ncontent-length: 15\r\nexpect: 100-continue\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut interim = [0; 25];
+        client.read_exact(&mut interim).await.unwrap();
+        assert_eq!(&interim, b"HTTP/1.1 100 Continue\r\n\r\n");
+        client.write_all(br#"{"name": "bob"}"#).await.unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(res.ends_with("3 is now bob after 0 visits"));
+
+        // Nothing to continue for a request that won't be read
+        let mut client = serve(Config::default().max_body_size(4));
+        client
+            .write_all(
+                b"PUT /user/3 HTTP/1.1\r\ncontent-length: 15\r\nexpect: 100-continue\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        assert!(res.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+    }
+
+    #[tokio::test]
+    async fn method_not_allowed() {
+        let mut client = serve(Config::default());
+        client
+            .write_all(b"POST /a HTTP/1.1\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        assert!(res.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(res.contains("Allow: GET, HEAD, OPTIONS"));
+    }
+
+    #[tokio::test]
+    async fn serve_dir() {
+        let mut client = serve(Config::default());
+        client
+            .write_all(b"GET /files/404.html HTTP/1.1\r\n\r\nHEAD /files/404.html HTTP/1.1\r\n\r\nGET /files/..%2FCargo.toml HTTP/1.1\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let page = std::fs::read_to_string("static/404.html").unwrap();
+        let length = format!("\r\nContent-Length: {}\r\n", page.len());
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        let mut responses = res.split("HTTP/1.1 ").skip(1);
+        let get = responses.next().unwrap();
+        assert!(get.starts_with("200 OK") && get.contains(&length) && get.ends_with(&page));
+        let head = responses.next().unwrap();
+        assert!(head.starts_with("200 OK") && head.contains(&length) && head.ends_with("\r\n\r\n"));
+        assert!(responses.next().unwrap().starts_with("403 Forbidden"));
+    }
+
+    #[tokio::test]
+    async fn not_modified() {
+        let modified = Response::new().serve_file("static/404.html");
+        let etag = modified.headers().get("ETag").unwrap();
+        let mut client = serve(Config::default());
+        client
+            .write_all(
+                format!(
+                    "GET /files/404.html HTTP/1.1\r\nif-none-match: {}\r\n\r\nPOST /files/404.html HTTP/1.1\r\nif-none-match: *\r\nconnection: close\r\n\r\n",
+                    etag
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        let mut responses = res.split("HTTP/1.1 ").skip(1);
+        let not_modified = responses.next().unwrap();
+        assert!(not_modified.starts_with("304 Not Modified"));
+        assert!(not_modified.ends_with("\r\n\r\n"));
+        assert!(responses
+            .next()
+            .unwrap()
+            .starts_with("405 Method Not Allowed"));
+    }
+
+    #[tokio::test]
+    async fn head_and_options() {
+        let mut client = serve(Config::default());
+        client
+            .write_all(b"HEAD /a HTTP/1.1\r\n\r\nOPTIONS /a HTTP/1.1\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        let (head, options) = res.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Content-Length: 7"));
+        assert!(options.starts_with("HTTP/1.1 204 No Content\r\n"));
+        assert!(options.contains("Allow: GET, HEAD, OPTIONS"));
+        assert!(!options.contains("Content-Length"));
+        assert!(options.ends_with("\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn bad_request() {
+        let mut client = serve(Config::default());
+        client
+            .write_all(b"GET /a HTTP/1.1\r\ncookie: bad\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        assert!(res.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(res.ends_with("invalid cookie"));
+
+        let mut client = serve(Config::default());
+        client.write_all(b"BREW /a HTTP/1.1\r\n\r\n").await.unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        assert!(res.starts_with("HTTP/1.1 501 Not Implemented\r\n"));
+
+        let mut client = serve(Config::default());
+        client
+            .write_all(b"POST /a HTTP/1.1\r\ncontent-length: 5\r\ntransfer-encoding: chunked\r\n\r\n0\r\n\r\nGET /a HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        assert!(res.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(res.contains("\r\nConnection: close\r\n"));
+        assert_eq!(res.matches("HTTP/1.1").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn unread_bodies() {
+        // Bodies nothing asked for can't fail the request
+        let mut client = serve(Config::default());
+        client
+            .write_all(b"POST /nope/x HTTP/1.1\r\ncontent-type: application/x-www-form-urlencoded\r\ncontent-length: 1\r\n\r\n%")
+            .await
+            .unwrap();
+        client
+            .write_all(b"GET /a HTTP/1.1\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        assert!(res.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(res.ends_with("hello a"));
+    }
+
+    #[tokio::test]
+    async fn stream() {
+        let mut client = serve(Config::default());
+        client
+            .write_all(
+                b"GET /count/3 HTTP/1.1\r\n\r\nGET /count/3 HTTP/1.0\r\nconnection: keep-alive\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        let (chunked, raw) = res.split_once("0\r\n\r\n").unwrap();
+        assert!(chunked.contains("Transfer-Encoding: chunked"));
+        assert!(chunked.ends_with("\r\n\r\n1\r\n0\r\n1\r\n1\r\n1\r\n2\r\n"));
+        assert!(raw.contains("Connection: close"));
+        assert!(raw.ends_with("\r\n\r\n012"));
+    }
+
+    #[tokio::test]
+    async fn idle_timeout() {
+        let mut client = serve(Config::default().keep_alive_timeout(Duration::from_millis(10)));
+        client
+            .write_all(b"GET /a HTTP/1.0\r\nconnection: keep-alive\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        assert!(res.contains("hello a"));
+    }
+
+    #[tokio::test]
+    async fn read_timeout() {
+        // A request that's slow to arrive isn't cut off by the idle timeout
+        let mut client = serve(Config::default().keep_alive_timeout(Duration::from_millis(10)));
+        client.write_all(b"GET /a HTTP/1.1\r\n").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        client
+            .write_all(b"connection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(res.ends_with("hello a"));
+
+        let mut client = serve(Config::default().read_timeout(Duration::from_millis(10)));
+        client
+            .write_all(b"PUT /user/3 HTTP/1.1\r\ncontent-length: 20\r\n\r\n{")
+            .await
+            .unwrap();
+
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        assert!(res.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+        assert!(res.contains("\r\nConnection: close\r\n"));
     }
 }
@@ -21,9 +21,9 @@ pub enum SameSite {
     None,
 }
 
-impl ToString for SameSite {
-    fn to_string(&self) -> String {
-        format!("{:?}", self)
+impl std::fmt::Display for SameSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
     }
 }
 
@@ -0,0 +1,327 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde::de::DeserializeOwned;
+use serde_json::json;
+
+use crate::{
+    io::{multipart::Multipart, urlencoded::MultiMap},
+    HeaderMap, IntoResponse, Request, Response, StatusCode,
+};
+
+/// A value that can be pulled out of a request to be passed to a handler. If it can't, the
+/// returned response is sent instead and the handler isn't run.
+pub trait FromRequest: Sized {
+    /// Whether this would rather take ownership of the request than borrow it.
+    const TAKES_REQUEST: bool = false;
+
+    fn from_request(req: &Request) -> Result<Self, Response>;
+
+    /// Extracts from a request no other argument needs anymore, which is how handlers are given
+    /// their `Request` without cloning it. Only called when [`FromRequest::TAKES_REQUEST`] is set.
+    fn from_request_owned(req: Request) -> Result<Self, Response> {
+        Self::from_request(&req)
+    }
+}
+
+fn reject(status: StatusCode, msg: impl ToString) -> Response {
+    Response::new().status(status).content(msg.to_string())
+}
+
+/// Describes why a body didn't fit the type it was deserialized into.
+fn unprocessable(e: impl ToString) -> Response {
+    let body = json!({ "error": e.to_string() });
+    (StatusCode::UnprocessableEntity, body).into_response()
+}
+
+impl FromRequest for Request {
+    const TAKES_REQUEST: bool = true;
+
+    fn from_request(req: &Request) -> Result<Self, Response> {
+        Ok(req.clone())
+    }
+
+    fn from_request_owned(req: Request) -> Result<Self, Response> {
+        Ok(req)
+    }
+}
+
+/// A blank response to build on, as handlers taking `(Request, Response)` always have.
+impl FromRequest for Response {
+    fn from_request(_: &Request) -> Result<Self, Response> {
+        Ok(Response::new())
+    }
+}
+
+impl<T: FromRequest> FromRequest for Option<T> {
+    const TAKES_REQUEST: bool = T::TAKES_REQUEST;
+
+    fn from_request(req: &Request) -> Result<Self, Response> {
+        Ok(T::from_request(req).ok())
+    }
+
+    fn from_request_owned(req: Request) -> Result<Self, Response> {
+        Ok(T::from_request_owned(req).ok())
+    }
+}
+
+/// The route's params, keyed by name.
+pub struct Params(pub HashMap<String, String>);
+
+impl FromRequest for Params {
+    fn from_request(req: &Request) -> Result<Self, Response> {
+        Ok(Params(req.params.clone()))
+    }
+}
+
+/// The route's params deserialized by name into `T`, which should be a struct or map. Fails with
+/// 400 Bad Request if a param is missing or doesn't parse.
+pub struct Path<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Path<T> {
+    fn from_request(req: &Request) -> Result<Self, Response> {
+        req.params
+            .iter()
+            .collect::<MultiMap>()
+            .deserialize()
+            .map(Path)
+            .map_err(|e| reject(StatusCode::BadRequest, e))
+    }
+}
+
+/// The query string deserialized into `T`, which should be a struct or map. Fails with 400 Bad
+/// Request if a pair is missing or doesn't parse. Use [`Request::query`] directly to read keys
+/// that repeat.
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Query<T> {
+    fn from_request(req: &Request) -> Result<Self, Response> {
+        req.query
+            .deserialize()
+            .map(Query)
+            .map_err(|e| reject(StatusCode::BadRequest, e))
+    }
+}
+
+/// The request's headers.
+pub struct Headers(pub HeaderMap);
+
+impl FromRequest for Headers {
+    fn from_request(req: &Request) -> Result<Self, Response> {
+        Ok(Headers(req.headers.clone()))
+    }
+}
+
+/// The cookies sent with the request, keyed by name.
+pub struct Cookies(pub HashMap<String, String>);
+
+impl FromRequest for Cookies {
+    fn from_request(req: &Request) -> Result<Self, Response> {
+        Ok(Cookies(req.cookies.clone()))
+    }
+}
+
+/// The JSON body deserialized into `T`. Fails with 415 Unsupported Media Type if the request
+/// isn't JSON and 422 Unprocessable Entity, with a JSON body describing what didn't fit, if it
+/// doesn't fit `T`.
+pub struct Json<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Json<T> {
+    fn from_request(req: &Request) -> Result<Self, Response> {
+        if !req.is_json() {
+            return Err(reject(
+                StatusCode::UnsupportedMediaType,
+                "expected a JSON body",
+            ));
+        }
+        req.json().map(Json).map_err(unprocessable)
+    }
+}
+
+/// The urlencoded form body deserialized into `T`. Fails like [`Json`], with 415 Unsupported
+/// Media Type if the request isn't a form and 422 Unprocessable Entity if it isn't validly
+/// encoded or doesn't fit `T`.
+pub struct Form<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Form<T> {
+    fn from_request(req: &Request) -> Result<Self, Response> {
+        if !req.is_form() {
+            return Err(reject(
+                StatusCode::UnsupportedMediaType,
+                "expected a form body",
+            ));
+        }
+        let form = req
+            .form()
+            .ok_or_else(|| unprocessable("invalid urlencoded body"))?;
+        form.deserialize().map(Form).map_err(unprocessable)
+    }
+}
+
+/// The fields and files of a `multipart/form-data` body. Fails with 415 Unsupported Media Type if
+/// the request isn't multipart.
+impl FromRequest for Multipart {
+    fn from_request(req: &Request) -> Result<Self, Response> {
+        req.multipart.clone().ok_or_else(|| {
+            reject(
+                StatusCode::UnsupportedMediaType,
+                "expected a multipart body",
+            )
+        })
+    }
+}
+
+/// A value shared with [`App::state`](crate::app::App::state). Fails with 500 Internal Server
+/// Error if the app never set one of this type.
+pub struct State<T>(pub Arc<T>);
+
+impl<T: Send + Sync + 'static> FromRequest for State<T> {
+    fn from_request(req: &Request) -> Result<Self, Response> {
+        req.state().map(State).ok_or_else(|| {
+            reject(
+                StatusCode::InternalServerError,
+                format!("no state of type {}", std::any::type_name::<T>()),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct User {
+        id: u32,
+        name: String,
+    }
+
+    fn request(raw: &str, params: &[(&str, &str)]) -> Request {
+        let mut req = Request::try_from(raw.to_string()).unwrap();
+        req.params = params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        req
+    }
+
+    #[test]
+    fn path() {
+        let req = request("GET /", &[("id", "7"), ("name", "bob")]);
+        let Path(user) = Path::<User>::from_request(&req).ok().unwrap();
+        assert_eq!(user.id, 7);
+        assert_eq!(user.name, "bob");
+
+        let req = request("GET /", &[("id", "seven"), ("name", "bob")]);
+        assert!(Path::<User>::from_request(&req).is_err());
+        assert!(Option::<Path<User>>::from_request(&req)
+            .ok()
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn query() {
+        let req = request("GET /users?id=7&name=bob%20smith", &[]);
+        let Query(user) = Query::<User>::from_request(&req).ok().unwrap();
+        assert_eq!(user.id, 7);
+        assert_eq!(user.name, "bob smith");
+
+        let req = request("GET /users?id=7", &[]);
+        assert!(Query::<User>::from_request(&req).is_err());
+    }
+
+    #[test]
+    fn json() {
+        let req = request(
+            "POST / HTTP/1.1\ncontent-type: application/json\n\n{\"id\": 1, \"name\": \"a\"}",
+            &[],
+        );
+        let Json(user) = Json::<User>::from_request(&req).ok().unwrap();
+        assert_eq!(user.id, 1);
+
+        let req = request(
+            "POST / HTTP/1.1\ncontent-type: application/json\n\n{\"id\": 1}",
+            &[],
+        );
+        assert!(Json::<User>::from_request(&req).is_err());
+
+        let req = request(
+            "POST / HTTP/1.1\ncontent-type: application/json\n\n{\"id\": \"1\", \"name\": \"a\"}",
+            &[],
+        );
+        assert!(Json::<User>::from_request(&req).is_err());
+
+        let req = request("POST /", &[]);
+        assert!(Json::<User>::from_request(&req).is_err());
+    }
+
+    #[test]
+    fn form() {
+        let req = request(
+            "POST / HTTP/1.1\ncontent-type: application/x-www-form-urlencoded\n\nid=1&name=a+b",
+            &[],
+        );
+        let Form(user) = Form::<User>::from_request(&req).ok().unwrap();
+        assert_eq!(user.id, 1);
+        assert_eq!(user.name, "a b");
+
+        let req = request(
+            "POST / HTTP/1.1\ncontent-type: application/x-www-form-urlencoded\n\nid=one&name=a",
+            &[],
+        );
+        assert!(Form::<User>::from_request(&req).is_err());
+
+        let req = request(
+            "POST / HTTP/1.1\ncontent-type: application/x-www-form-urlencoded\n\nid=1&name=%",
+            &[],
+        );
+        let err = Form::<User>::from_request(&req).err().unwrap();
+        assert_eq!(
+            err.headers().get("Content-Type"),
+            Some("application/json; charset=utf-8")
+        );
+
+        let req = request(
+            "POST / HTTP/1.1\ncontent-type: application/json\n\n{\"id\": 1, \"name\": \"a\"}",
+            &[],
+        );
+        assert!(Form::<User>::from_request(&req).is_err());
+    }
+
+    #[tokio::test]
+    async fn owned_request() {
+        use crate::handler::IntoHandler;
+
+        // Each clone of the request holds another reference to its state
+        let clones = |req: &Request| (Arc::strong_count(&req.state) - 1).to_string();
+        let body = |res: Response| async move {
+            let mut out = Vec::new();
+            res.write_to(&mut out, false).await.unwrap();
+            let end = out.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+            String::from_utf8(out[end + 4..].to_vec()).unwrap()
+        };
+
+        let handler = (move |req: Request, res: Response| async move { res.content(clones(&req)) })
+            .into_handler();
+        let res = handler.call(request("GET /", &[]), Response::new()).await;
+        assert_eq!(body(res).await, "0");
+
+        let handler = (move |req: Request, Path(user): Path<User>| async move {
+            format!("{} {}", user.name, clones(&req))
+        })
+        .into_handler();
+        let req = request("GET /", &[("id", "7"), ("name", "bob")]);
+        assert_eq!(
+            body(handler.call(req, Response::new()).await).await,
+            "bob 0"
+        );
+
+        let handler =
+            (move |a: Request, b: Request| async move { format!("{} {}", clones(&a), clones(&b)) })
+                .into_handler();
+        let res = handler.call(request("GET /", &[]), Response::new()).await;
+        assert_eq!(body(res).await, "1 1");
+    }
+}
@@ -0,0 +1,104 @@
+use std::{future::Future, marker::PhantomData, pin::Pin};
+
+use crate::{extract::FromRequest, io::response::IntoResponse, Request, Response};
+
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Answers requests routed to an endpoint. Implement this by hand for types that carry their own
+/// data; async functions and closures are turned into handlers through [`IntoHandler`].
+pub trait Handler: Send + Sync + 'static {
+    fn call(&self, req: Request, res: Response) -> BoxFuture<Response>;
+}
+
+/// Anything that can be registered as an endpoint. This is implemented for [`Handler`]s and for
+/// async functions and closures whose arguments are all [`FromRequest`] extractors and whose
+/// output is [`IntoResponse`], so `async fn(Request, Response) -> Response` works as well as
+/// `async fn(Path<User>, Json<Update>) -> Result<String, StatusCode>`.
+pub trait IntoHandler<Args> {
+    fn into_handler(self) -> Box<dyn Handler>;
+}
+
+/// Marks handlers registered as themselves rather than through extractors.
+#[doc(hidden)]
+pub struct Direct;
+
+impl<H: Handler> IntoHandler<Direct> for H {
+    fn into_handler(self) -> Box<dyn Handler> {
+        Box::new(self)
+    }
+}
+
+struct FnHandler<F, Args> {
+    f: F,
+    args: PhantomData<fn() -> Args>,
+}
+
+macro_rules! impl_handler {
+    ($($ty:ident),*) => {
+        #[allow(non_snake_case, unused_variables, unused_mut)]
+        impl<F, Fut, R, $($ty,)*> Handler for FnHandler<F, ($($ty,)*)>
+        where
+            F: Fn($($ty),*) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = R> + Send + 'static,
+            R: IntoResponse,
+            $($ty: FromRequest + 'static,)*
+        {
+            fn call(&self, req: Request, _: Response) -> BoxFuture<Response> {
+                // Everything that borrows the request is extracted first, so the last argument
+                // wanting the request itself can have it moved in
+                $(
+                    let $ty = match $ty::TAKES_REQUEST {
+                        true => None,
+                        false => match $ty::from_request(&req) {
+                            Ok(value) => Some(value),
+                            Err(rejection) => return Box::pin(async { rejection }),
+                        },
+                    };
+                )*
+                let mut takers = 0 $(+ $ty::TAKES_REQUEST as usize)*;
+                let mut req = Some(req);
+                $(
+                    let $ty = match $ty {
+                        Some(value) => value,
+                        None => {
+                            takers -= 1;
+                            let value = match takers {
+                                0 => $ty::from_request_owned(req.take().unwrap()),
+                                _ => $ty::from_request(req.as_ref().unwrap()),
+                            };
+                            match value {
+                                Ok(value) => value,
+                                Err(rejection) => return Box::pin(async { rejection }),
+                            }
+                        }
+                    };
+                )*
+                let fut = (self.f)($($ty),*);
+                Box::pin(async move { fut.await.into_response() })
+            }
+        }
+
+        impl<F, Fut, R, $($ty,)*> IntoHandler<($($ty,)*)> for F
+        where
+            F: Fn($($ty),*) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = R> + Send + 'static,
+            R: IntoResponse,
+            $($ty: FromRequest + 'static,)*
+        {
+            fn into_handler(self) -> Box<dyn Handler> {
+                Box::new(FnHandler {
+                    f: self,
+                    args: PhantomData,
+                })
+            }
+        }
+    };
+}
+
+impl_handler!();
+impl_handler!(T1);
+impl_handler!(T1, T2);
+impl_handler!(T1, T2, T3);
+impl_handler!(T1, T2, T3, T4);
+impl_handler!(T1, T2, T3, T4, T5);
+impl_handler!(T1, T2, T3, T4, T5, T6);
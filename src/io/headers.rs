@@ -0,0 +1,136 @@
+use std::fmt::Debug;
+
+/// Header fields in the order they were added. Names are matched case-insensitively but keep the
+/// case they were given, values are kept exactly as sent apart from surrounding spaces and tabs,
+/// and a name may have any number of values. Carriage returns, line feeds and NULs are dropped from
+/// both, so a field can never spill into another one when it's written out.
+#[derive(PartialEq, Eq, Clone, Default)]
+pub struct HeaderMap(Vec<(String, String)>);
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The first value of `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &v[..])
+    }
+
+    /// Every value of `name`, in order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.0
+            .iter()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &v[..])
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Sets `name` to `value`, replacing any values it already has.
+    pub fn insert(&mut self, name: impl ToString, value: impl ToString) {
+        let name = name.to_string();
+        self.remove(&name);
+        self.append(name, value);
+    }
+
+    /// Adds another value for `name`, keeping any it already has.
+    pub fn append(&mut self, name: impl ToString, value: impl ToString) {
+        let value = sanitize(value.to_string());
+        let value = value.trim_matches([' ', '\t']).to_string();
+        self.0.push((sanitize(name.to_string()), value));
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.0.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+    }
+
+    /// Each name and value, with names repeated for each of their values.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (&k[..], &v[..]))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+fn sanitize(mut s: String) -> String {
+    s.retain(|c| !matches!(c, '\r' | '\n' | '\0'));
+    s
+}
+
+impl Debug for HeaderMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_insensitive() {
+        let mut headers = HeaderMap::new();
+        headers.append("Authorization", "  Bearer AbC ");
+        assert_eq!(headers.get("authorization"), Some("Bearer AbC"));
+        assert_eq!(headers.get("AUTHORIZATION"), Some("Bearer AbC"));
+        assert!(headers.contains("authorization"));
+        assert_eq!(headers.iter().next(), Some(("Authorization", "Bearer AbC")));
+        assert_eq!(headers.get("accept"), None);
+
+        // Only spaces and tabs are optional whitespace
+        headers.append("X-Padded", "\t\u{a0}value\u{3000} ");
+        assert_eq!(headers.get("x-padded"), Some("\u{a0}value\u{3000}"));
+    }
+
+    #[test]
+    fn multiple_values() {
+        let mut headers = HeaderMap::new();
+        headers.append("Vary", "Accept");
+        headers.append("Content-Type", "text/plain");
+        headers.append("vary", "Cookie");
+        assert_eq!(headers.get("vary"), Some("Accept"));
+        assert_eq!(
+            headers.get_all("Vary").collect::<Vec<_>>(),
+            vec!["Accept", "Cookie"]
+        );
+        assert_eq!(headers.len(), 3);
+
+        headers.insert("VARY", "*");
+        assert_eq!(headers.get_all("vary").collect::<Vec<_>>(), vec!["*"]);
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            vec![("Content-Type", "text/plain"), ("VARY", "*")]
+        );
+
+        headers.remove("content-type");
+        headers.remove("vary");
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn line_breaks() {
+        let mut headers = HeaderMap::new();
+        headers.insert("Location", "/next\r\nSet-Cookie: session=stolen");
+        headers.append("X-\nInjected\0", "a\rb");
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            vec![
+                ("Location", "/nextSet-Cookie: session=stolen"),
+                ("X-Injected", "ab")
+            ]
+        );
+        assert!(!headers.contains("set-cookie"));
+    }
+}
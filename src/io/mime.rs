@@ -0,0 +1,64 @@
+use phf::phf_map;
+
+/// Sent for files whose extension isn't known.
+pub const DEFAULT: &str = "application/octet-stream";
+
+/// The content-type of a file with `extension`, which is matched without its dot and ignoring
+/// case.
+pub fn from_extension(extension: &str) -> Option<&'static str> {
+    MIME_TYPES.get(&extension.to_ascii_lowercase()[..]).copied()
+}
+
+static MIME_TYPES: phf::Map<&'static str, &'static str> = phf_map! {
+    "html" => "text/html; charset=utf-8",
+    "htm" => "text/html; charset=utf-8",
+    "css" => "text/css; charset=utf-8",
+    "js" => "text/javascript; charset=utf-8",
+    "mjs" => "text/javascript; charset=utf-8",
+    "txt" => "text/plain; charset=utf-8",
+    "csv" => "text/csv; charset=utf-8",
+    "md" => "text/markdown; charset=utf-8",
+    "xml" => "application/xml; charset=utf-8",
+    "json" => "application/json; charset=utf-8",
+    "map" => "application/json; charset=utf-8",
+    "webmanifest" => "application/manifest+json; charset=utf-8",
+    "svg" => "image/svg+xml; charset=utf-8",
+    "png" => "image/png",
+    "jpg" => "image/jpeg",
+    "jpeg" => "image/jpeg",
+    "gif" => "image/gif",
+    "webp" => "image/webp",
+    "avif" => "image/avif",
+    "ico" => "image/x-icon",
+    "bmp" => "image/bmp",
+    "woff" => "font/woff",
+    "woff2" => "font/woff2",
+    "ttf" => "font/ttf",
+    "otf" => "font/otf",
+    "mp3" => "audio/mpeg",
+    "ogg" => "audio/ogg",
+    "wav" => "audio/wav",
+    "mp4" => "video/mp4",
+    "webm" => "video/webm",
+    "pdf" => "application/pdf",
+    "zip" => "application/zip",
+    "gz" => "application/gzip",
+    "tar" => "application/x-tar",
+    "wasm" => "application/wasm",
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        assert_eq!(from_extension("css"), Some("text/css; charset=utf-8"));
+        assert_eq!(from_extension("HTML"), Some("text/html; charset=utf-8"));
+        assert_eq!(from_extension("png"), Some("image/png"));
+        assert_eq!(from_extension("gz"), Some("application/gzip"));
+        assert_eq!(from_extension("Js"), Some("text/javascript; charset=utf-8"));
+        assert_eq!(from_extension("exe"), None);
+        assert_eq!(from_extension(""), None);
+    }
+}
@@ -1,3 +1,7 @@
+pub mod headers;
+pub mod mime;
+pub mod multipart;
 pub mod request;
 pub mod response;
 pub mod status;
+pub mod urlencoded;
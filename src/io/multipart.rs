@@ -0,0 +1,546 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+};
+
+use crate::{app::Config, io::request::ParseError};
+
+/// The most header bytes a single part may have.
+const MAX_PART_HEAD: usize = 8 * 1024;
+
+/// Numbers the temp files so that parts uploaded at the same time don't collide.
+static UPLOADS: AtomicUsize = AtomicUsize::new(0);
+
+/// The parts of a `multipart/form-data` body, in the order they were sent.
+#[derive(Clone, Debug, Default)]
+pub struct Multipart {
+    parts: Vec<Part>,
+}
+
+impl Multipart {
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
+
+    /// The value of the first plain field named `name`, if it's valid UTF-8 and small enough to
+    /// have been kept in memory.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields().find(|(n, _)| n == &name).map(|(_, v)| v)
+    }
+
+    /// The plain fields, which are the parts without a filename, whose values are valid UTF-8.
+    /// Fields too large to keep in memory are left out; read them through [`Multipart::parts`].
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.parts
+            .iter()
+            .filter(|p| !p.is_file())
+            .filter_map(|p| match &p.data {
+                Data::Memory(data) => Some((&p.name[..], std::str::from_utf8(data).ok()?)),
+                Data::File(_) => None,
+            })
+    }
+
+    /// The first file uploaded as `name`.
+    pub fn file(&self, name: &str) -> Option<&Part> {
+        self.files().find(|p| p.name == name)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &Part> {
+        self.parts.iter().filter(|p| p.is_file())
+    }
+}
+
+/// A single field or file. Parts larger than [`Config::max_part_memory`] are kept in a temp file
+/// under [`Config::upload_dir`], which is removed once every copy of the request is dropped.
+#[derive(Clone, Debug)]
+pub struct Part {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+
+    /// Every header sent with the part, keyed by lowercase name.
+    pub headers: HashMap<String, String>,
+    data: Data,
+    len: u64,
+}
+
+#[derive(Clone, Debug)]
+enum Data {
+    Memory(Vec<u8>),
+    File(Arc<TempFile>),
+}
+
+impl Part {
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The contents of the part, read back from disk if it was too large to keep in memory.
+    pub fn bytes(&self) -> io::Result<Cow<'_, [u8]>> {
+        match &self.data {
+            Data::Memory(data) => Ok(Cow::Borrowed(data)),
+            Data::File(file) => fs::read(&file.0).map(Cow::Owned),
+        }
+    }
+
+    /// Like [`Part::bytes`], but without blocking the thread on the disk.
+    pub async fn bytes_async(&self) -> io::Result<Cow<'_, [u8]>> {
+        match &self.data {
+            Data::Memory(data) => Ok(Cow::Borrowed(data)),
+            Data::File(file) => tokio::fs::read(&file.0).await.map(Cow::Owned),
+        }
+    }
+
+    /// Where the part is stored on disk, if it was too large to keep in memory.
+    pub fn path(&self) -> Option<&Path> {
+        match &self.data {
+            Data::Memory(_) => None,
+            Data::File(file) => Some(&file.0),
+        }
+    }
+
+    /// Copies the contents of the part to `path`.
+    pub fn persist(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        match &self.data {
+            Data::Memory(data) => fs::write(path, data),
+            Data::File(file) => fs::copy(&file.0, path).map(|_| ()),
+        }
+    }
+
+    /// Like [`Part::persist`], but without blocking the thread on the disk.
+    pub async fn persist_async(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        match &self.data {
+            Data::Memory(data) => tokio::fs::write(path, data).await,
+            Data::File(file) => tokio::fs::copy(&file.0, path).await.map(|_| ()),
+        }
+    }
+}
+
+/// A file that is deleted when dropped.
+#[derive(Debug)]
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let path = std::mem::take(&mut self.0);
+        // Requests are usually dropped on a runtime worker, which shouldn't wait on the disk
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(move || fs::remove_file(path))),
+            Err(_) => drop(fs::remove_file(path)),
+        }
+    }
+}
+
+/// The boundary of a `multipart/form-data` content-type, with any quotes removed.
+pub(crate) fn boundary(content_type: &str) -> Option<&str> {
+    let mut params = content_type.split(';');
+    let media_type = params.next()?.trim();
+    if !media_type.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .filter_map(|param| param.split_once('='))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, v)| v.trim().trim_matches('"'))
+        .filter(|b| !b.is_empty() && b.len() <= 70)
+}
+
+enum Stage {
+    Preamble,
+    Delimiter,
+    Headers,
+    Body,
+    End,
+}
+
+/// Parses a multipart body incrementally as it's read off the connection, so only the parts
+/// small enough to keep in memory ever are. Parsing doesn't touch the disk itself: data for parts
+/// that have moved to temp files is queued until [`Parser::flush`] writes it out.
+pub(crate) struct Parser {
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    stage: Stage,
+    parts: Vec<Part>,
+    spills: Vec<(Arc<TempFile>, Vec<u8>)>,
+    file: Option<(Arc<TempFile>, File)>,
+    received: bool,
+    max_part_size: u64,
+    max_part_memory: usize,
+    upload_dir: PathBuf,
+}
+
+impl Parser {
+    pub(crate) fn new(boundary: &str, config: &Config) -> Self {
+        Self {
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            // The first delimiter needn't follow a line break, so pretend there was one
+            buf: b"\r\n".to_vec(),
+            stage: Stage::Preamble,
+            parts: Vec::new(),
+            spills: Vec::new(),
+            file: None,
+            received: false,
+            max_part_size: config.max_part_size as u64,
+            max_part_memory: config.max_part_memory,
+            upload_dir: config.upload_dir.clone(),
+        }
+    }
+
+    pub(crate) fn feed(&mut self, data: &[u8]) -> Result<(), ParseError> {
+        self.received |= !data.is_empty();
+        self.buf.extend_from_slice(data);
+        loop {
+            match self.stage {
+                Stage::Preamble | Stage::Body => {
+                    let in_body = matches!(self.stage, Stage::Body);
+                    let Some(i) = find(&self.buf, &self.delimiter) else {
+                        // Hold back anything that could be the start of a delimiter
+                        let safe = self.buf.len().saturating_sub(self.delimiter.len() - 1);
+                        let data: Vec<u8> = self.buf.drain(..safe).collect();
+                        if in_body {
+                            self.write(&data)?;
+                        }
+                        return Ok(());
+                    };
+                    let data: Vec<u8> = self.buf.drain(..i + self.delimiter.len()).collect();
+                    if in_body {
+                        self.write(&data[..i])?;
+                    }
+                    self.stage = Stage::Delimiter;
+                }
+                Stage::Delimiter => {
+                    if self.buf.starts_with(b"--") {
+                        self.stage = Stage::End;
+                        continue;
+                    }
+                    let Some(end) = find(&self.buf, b"\r\n") else {
+                        if self.buf.len() > MAX_PART_HEAD {
+                            return Err(ParseError::InvalidBody);
+                        }
+                        return Ok(());
+                    };
+                    // Only whitespace may follow the boundary on its line
+                    if self.buf[..end].iter().any(|b| !matches!(b, b' ' | b'\t')) {
+                        return Err(ParseError::InvalidBody);
+                    }
+                    self.buf.drain(..end + 2);
+                    self.stage = Stage::Headers;
+                }
+                Stage::Headers => {
+                    let end = match self.buf.starts_with(b"\r\n") {
+                        true => Some(0),
+                        false => find(&self.buf, b"\r\n\r\n").map(|i| i + 2),
+                    };
+                    let Some(end) = end else {
+                        if self.buf.len() > MAX_PART_HEAD {
+                            return Err(ParseError::InvalidBody);
+                        }
+                        return Ok(());
+                    };
+                    let head: Vec<u8> = self.buf.drain(..end + 2).collect();
+                    let head = std::str::from_utf8(&head).map_err(|_| ParseError::InvalidBody)?;
+                    self.parts.push(part(head)?);
+                    self.stage = Stage::Body;
+                }
+                Stage::End => {
+                    // Anything after the last boundary is an epilogue to be ignored
+                    self.buf.clear();
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Whether no body has been fed at all.
+    pub(crate) fn is_empty(&self) -> bool {
+        !self.received
+    }
+
+    pub(crate) fn finish(self) -> Result<Multipart, ParseError> {
+        match self.stage {
+            Stage::End => Ok(Multipart { parts: self.parts }),
+            _ => Err(ParseError::InvalidBody),
+        }
+    }
+
+    /// Writes out the data queued for temp files, without blocking on the disk.
+    pub(crate) async fn flush(&mut self) -> Result<(), ParseError> {
+        for (temp, data) in std::mem::take(&mut self.spills) {
+            // Parts arrive one after another, so a file is never returned to once left
+            if !self
+                .file
+                .as_ref()
+                .is_some_and(|(open, _)| Arc::ptr_eq(open, &temp))
+            {
+                let file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&temp.0)
+                    .await
+                    .map_err(|_| ParseError::UploadFailed)?;
+                self.file = Some((temp, file));
+            }
+            let (_, file) = self.file.as_mut().ok_or(ParseError::UploadFailed)?;
+            file.write_all(&data)
+                .await
+                .map_err(|_| ParseError::UploadFailed)?;
+            file.flush().await.map_err(|_| ParseError::UploadFailed)?;
+        }
+        Ok(())
+    }
+
+    /// Appends to the current part, moving it to a temp file once it's a file too large to keep
+    /// in memory.
+    fn write(&mut self, data: &[u8]) -> Result<(), ParseError> {
+        let part = self.parts.last_mut().ok_or(ParseError::InvalidBody)?;
+        part.len += data.len() as u64;
+        if part.len > self.max_part_size {
+            return Err(ParseError::BodyTooLarge);
+        }
+
+        if let Data::Memory(memory) = &mut part.data {
+            if memory.len() + data.len() <= self.max_part_memory {
+                memory.extend_from_slice(data);
+                return Ok(());
+            }
+            let path = self.upload_dir.join(format!(
+                "upload-{}-{}",
+                process::id(),
+                UPLOADS.fetch_add(1, Ordering::Relaxed)
+            ));
+            let file = Arc::new(TempFile(path));
+            if let Data::Memory(memory) =
+                std::mem::replace(&mut part.data, Data::File(file.clone()))
+            {
+                self.spills.push((file, memory));
+            }
+        }
+        let Data::File(file) = &part.data else {
+            return Err(ParseError::InvalidBody);
+        };
+        match self.spills.last_mut() {
+            Some((last, queued)) if Arc::ptr_eq(last, file) => queued.extend_from_slice(data),
+            _ => self.spills.push((file.clone(), data.to_vec())),
+        }
+        Ok(())
+    }
+}
+
+/// Starts a part from its headers, which have to include a `Content-Disposition` naming it.
+fn part(head: &str) -> Result<Part, ParseError> {
+    let mut headers = HashMap::new();
+    for line in head.split("\r\n").filter(|line| !line.is_empty()) {
+        let (key, value) = line.split_once(':').ok_or(ParseError::InvalidBody)?;
+        headers.insert(key.trim().to_lowercase(), value.trim().to_string());
+    }
+
+    let disposition = headers
+        .get("content-disposition")
+        .ok_or(ParseError::InvalidBody)?;
+    let params = disposition_params(disposition);
+    Ok(Part {
+        name: params.get("name").ok_or(ParseError::InvalidBody)?.clone(),
+        filename: params.get("filename").cloned(),
+        content_type: headers.get("content-type").cloned(),
+        headers,
+        data: Data::Memory(Vec::new()),
+        len: 0,
+    })
+}
+
+/// The parameters of a `Content-Disposition` value such as `form-data; name="a"`, whose values
+/// may be quoted strings containing `;` or escaped quotes.
+fn disposition_params(value: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut chars = value.chars().peekable();
+    // Skip the disposition type
+    chars.by_ref().take_while(|c| c != &';').for_each(drop);
+
+    loop {
+        let key: String = chars.by_ref().take_while(|c| c != &'=').collect();
+        let key = key.trim().to_lowercase();
+        if key.is_empty() {
+            return params;
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => value.extend(chars.next()),
+                    c => value.push(c),
+                }
+            }
+        }
+        value.extend(chars.by_ref().take_while(|c| c != &';'));
+        params.insert(key, value.trim_end().to_string());
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &str = "preamble\r\n\
+                        --XyZ\r\n\
+                        Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+                        hello; world\r\n\
+                        --XyZ  \r\n\
+                        content-disposition: form-data; name=\"upload\"; filename=\"a \\\"b\\\".txt\"\r\n\
+                        Content-Type: text/plain\r\n\r\n\
+                        line one\r\n--XY line two\r\n\
+                        --XyZ--\r\n\
+                        epilogue";
+
+    fn config() -> Config {
+        Config::default()
+            .max_part_memory(16)
+            .upload_dir(std::env::temp_dir())
+    }
+
+    async fn parse(body: &[u8], config: &Config, chunk: usize) -> Result<Multipart, ParseError> {
+        let mut parser = Parser::new("XyZ", config);
+        for data in body.chunks(chunk) {
+            parser.feed(data)?;
+            parser.flush().await?;
+        }
+        parser.finish()
+    }
+
+    #[test]
+    fn boundaries() {
+        assert_eq!(boundary("multipart/form-data; boundary=abc"), Some("abc"));
+        assert_eq!(
+            boundary("Multipart/Form-Data;charset=utf-8; Boundary=\"a b\""),
+            Some("a b")
+        );
+        assert_eq!(boundary("multipart/form-data"), None);
+        assert_eq!(boundary("multipart/mixed; boundary=abc"), None);
+        assert_eq!(boundary("multipart/form-data; boundary="), None);
+    }
+
+    #[tokio::test]
+    async fn parse_parts() {
+        for chunk in [1, 3, 7, BODY.len()] {
+            let multipart = parse(BODY.as_bytes(), &config(), chunk).await.unwrap();
+            assert_eq!(multipart.parts().len(), 2);
+            assert_eq!(multipart.field("title"), Some("hello; world"));
+            assert_eq!(multipart.field("upload"), None);
+
+            let file = multipart.file("upload").unwrap();
+            assert_eq!(file.filename.as_deref(), Some("a \"b\".txt"));
+            assert_eq!(file.content_type.as_deref(), Some("text/plain"));
+            assert_eq!(file.headers["content-type"], "text/plain");
+            assert_eq!(file.len(), 23);
+            assert_eq!(&file.bytes().unwrap()[..], b"line one\r\n--XY line two");
+
+            // Larger than the memory limit, so it's kept on disk until dropped
+            let path = file.path().unwrap().to_path_buf();
+            assert!(path.starts_with(std::env::temp_dir()));
+            assert!(path.exists());
+            assert_eq!(
+                &file.bytes_async().await.unwrap()[..],
+                b"line one\r\n--XY line two"
+            );
+            let copy = multipart.clone();
+            drop(multipart);
+            assert!(path.exists());
+            drop(copy);
+            assert!(removed(&path).await);
+        }
+
+        // Fields are moved to disk just like files
+        let multipart = parse(BODY.as_bytes(), &config().max_part_memory(4), 5)
+            .await
+            .unwrap();
+        let title = &multipart.parts()[0];
+        assert_eq!(multipart.field("title"), None);
+        assert!(title.path().is_some());
+        assert_eq!(&title.bytes().unwrap()[..], b"hello; world");
+
+        let copy = std::env::temp_dir().join(format!("web-persist-{}", process::id()));
+        title.persist_async(&copy).await.unwrap();
+        assert_eq!(fs::read(&copy).unwrap(), b"hello; world");
+        fs::remove_file(copy).unwrap();
+    }
+
+    /// Waits for a dropped temp file to be removed in the background.
+    async fn removed(path: &Path) -> bool {
+        for _ in 0..100 {
+            if !path.exists() {
+                return true;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn memory() {
+        let multipart = parse(BODY.as_bytes(), &Config::default(), 5).await.unwrap();
+        let file = multipart.file("upload").unwrap();
+        assert_eq!(file.path(), None);
+        assert_eq!(&file.bytes().unwrap()[..], b"line one\r\n--XY line two");
+
+        let multipart = parse(b"--XyZ--", &Config::default(), 5).await.unwrap();
+        assert!(multipart.parts().is_empty());
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        let config = config().max_part_size(21);
+        let err = parse(BODY.as_bytes(), &config, 5).await.unwrap_err();
+        assert_eq!(err, ParseError::BodyTooLarge);
+
+        let config = config.upload_dir(PathBuf::from("/nonexistent/uploads"));
+        let err = parse(BODY.as_bytes(), &config.max_part_size(100), 5)
+            .await
+            .unwrap_err();
+        assert_eq!(err, ParseError::UploadFailed);
+    }
+
+    #[tokio::test]
+    async fn invalid() {
+        let config = Config::default();
+        let invalid = [
+            &b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nunterminated"[..],
+            b"--XyZ\r\nContent-Type: text/plain\r\n\r\nno name\r\n--XyZ--",
+            b"--XyZ\r\nContent-Disposition: form-data\r\n\r\nno name\r\n--XyZ--",
+            b"--XyZjunk\r\n\r\n--XyZ--",
+            b"--XyZ\r\nno colon\r\n\r\n--XyZ--",
+            b"no boundary at all",
+        ];
+        for body in invalid {
+            assert_eq!(
+                parse(body, &config, 4).await.unwrap_err(),
+                ParseError::InvalidBody
+            );
+        }
+    }
+}
@@ -1,82 +1,612 @@
-use std::{collections::HashMap, fmt::Debug};
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
 
+use serde::de::DeserializeOwned;
 use serde_json::Value;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time::timeout,
+};
 
-use crate::{app::Method, route::Route};
+use crate::{
+    app::{Config, Method, State},
+    io::{
+        headers::HeaderMap,
+        multipart::{self, Multipart, Parser},
+        urlencoded::MultiMap,
+    },
+    route::Route,
+    StatusCode,
+};
+
+/// Why a request couldn't be read. Each error maps to the status the client is answered with.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ParseError {
+    EmptyRequest,
+    InvalidStartLine,
+    InvalidMethod,
+    UnsupportedVersion,
+    UriTooLong,
+    InvalidHeader,
+    InvalidCookie,
+    HeadersTooLarge,
+    InvalidContentLength,
+    ConflictingLength,
+    UnsupportedTransferEncoding,
+    InvalidChunk,
+    BodyTooLarge,
+    InvalidBody,
+    InvalidEncoding,
+    InvalidPercentEncoding,
+    Incomplete,
+    ConnectionError,
+    UploadFailed,
+    Timeout,
+}
+
+impl ParseError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ParseError::InvalidMethod | ParseError::UnsupportedTransferEncoding => {
+                StatusCode::NotImplemented
+            }
+            ParseError::UnsupportedVersion => StatusCode::HTTPVersionNotSupported,
+            ParseError::UriTooLong => StatusCode::URITooLong,
+            ParseError::HeadersTooLarge => StatusCode::RequestHeaderFieldsTooLarge,
+            ParseError::BodyTooLarge => StatusCode::PayloadTooLarge,
+            ParseError::UploadFailed => StatusCode::InternalServerError,
+            ParseError::Timeout => StatusCode::RequestTimeout,
+            _ => StatusCode::BadRequest,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ParseError::EmptyRequest => "empty request",
+            ParseError::InvalidStartLine => "invalid start-line",
+            ParseError::InvalidMethod => "invalid method",
+            ParseError::UnsupportedVersion => "unsupported version",
+            ParseError::UriTooLong => "uri too long",
+            ParseError::InvalidHeader => "invalid header",
+            ParseError::InvalidCookie => "invalid cookie",
+            ParseError::HeadersTooLarge => "headers too large",
+            ParseError::InvalidContentLength => "invalid content-length",
+            ParseError::ConflictingLength => "both content-length and transfer-encoding",
+            ParseError::UnsupportedTransferEncoding => "unsupported transfer-encoding",
+            ParseError::InvalidChunk => "invalid chunk",
+            ParseError::BodyTooLarge => "body too large",
+            ParseError::InvalidBody => "invalid body",
+            ParseError::InvalidEncoding => "invalid encoding",
+            ParseError::InvalidPercentEncoding => "invalid percent-encoding",
+            ParseError::Incomplete => "incomplete request",
+            ParseError::ConnectionError => "connection error",
+            ParseError::UploadFailed => "couldn't store upload",
+            ParseError::Timeout => "request timed out",
+        })
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl TryFrom<&str> for Version {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Ok(match s {
+            "HTTP/1.0" => Version::Http10,
+            "HTTP/1.1" => Version::Http11,
+            s if s.starts_with("HTTP/") => return Err(ParseError::UnsupportedVersion),
+            _ => return Err(ParseError::InvalidStartLine),
+        })
+    }
+}
 
 #[derive(Clone)]
 pub struct Request {
     pub method: Method,
+
+    /// The request-target exactly as it was sent, before the path is decoded and split into
+    /// [`Request::route`] and the query into [`Request::query`].
+    pub target: String,
     pub route: Route,
+    pub version: Version,
     pub params: HashMap<String, String>,
-    pub headers: HashMap<String, String>,
+
+    /// The pairs in the request-target's query string, after the `?`.
+    pub query: MultiMap,
+    pub headers: HeaderMap,
+
+    /// Fields sent after a chunked body. They're kept apart from `headers` since they arrive too
+    /// late to affect how the request is read.
+    pub trailers: HeaderMap,
     pub cookies: HashMap<String, String>,
-    pub body: Value,
+
+    /// The fields and files of a `multipart/form-data` body, which is never kept in `raw_body`.
+    pub multipart: Option<Multipart>,
+
+    /// The body exactly as it was sent, after any transfer-encoding is removed.
+    pub raw_body: Vec<u8>,
+    pub(crate) state: Arc<State>,
 }
 
 impl Request {
-    pub fn populate_params(&mut self, route: &Route) {
-        self.params = route.params(self);
+    /// The value of type `T` shared with [`App::state`](crate::app::App::state), if there is one.
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.state.get()
     }
-}
 
-impl TryFrom<&[u8; 1024]> for Request {
-    type Error = &'static str;
+    /// Whether the body is declared to be JSON, either as `application/json` or a type with a
+    /// `+json` suffix.
+    pub fn is_json(&self) -> bool {
+        self.media_type()
+            .is_some_and(|t| t == "application/json" || t.ends_with("+json"))
+    }
 
-    fn try_from(buffer: &[u8; 1024]) -> Result<Self, Self::Error> {
-        Self::try_from(String::from_utf8(buffer.to_vec()).unwrap())
+    /// Whether the body is declared to be an urlencoded form, as HTML forms send by default.
+    pub fn is_form(&self) -> bool {
+        self.media_type().as_deref() == Some("application/x-www-form-urlencoded")
     }
-}
 
-impl TryFrom<String> for Request {
-    type Error = &'static str;
+    /// The content-type without its parameters, in lowercase.
+    fn media_type(&self) -> Option<String> {
+        let content_type = self.headers.get("content-type")?;
+        let media_type = content_type.split(';').next().unwrap_or_default();
+        Some(media_type.trim().to_ascii_lowercase())
+    }
 
-    fn try_from(req: String) -> Result<Self, Self::Error> {
-        let mut lines = req.lines();
-        let mut header = lines.next().ok_or("empty request")?.split(' ');
-        let method = header.next().ok_or("invalid start-line")?;
-        let route = header.next().ok_or("invalid start-line")?;
+    /// The body as JSON, or `Value::Null` if it isn't declared as JSON or doesn't parse. It's
+    /// parsed anew on each call, so use [`Request::json`] or the [`Json`](crate::extract::Json)
+    /// extractor to find out why a body is invalid.
+    pub fn body(&self) -> Value {
+        match self.is_json() {
+            true => self.json().unwrap_or(Value::Null),
+            false => Value::Null,
+        }
+    }
 
-        let mut headers = HashMap::new();
-        let mut cookies = HashMap::new();
+    /// Deserializes the raw body as JSON, regardless of its declared content-type.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_slice(&self.raw_body)
+    }
 
-        for line in lines.by_ref() {
-            if line.is_empty() {
-                break; // End of headers
-            }
+    /// Decodes the raw body as urlencoded pairs, regardless of its declared content-type. Returns
+    /// `None` if it isn't validly encoded.
+    pub fn form(&self) -> Option<MultiMap> {
+        std::str::from_utf8(&self.raw_body)
+            .ok()
+            .and_then(MultiMap::parse)
+    }
 
-            let (key, value) = line.split_once(':').ok_or("invalid header")?;
-            if key.to_lowercase() == "cookie" {
-                for cookie in value.split(';') {
-                    let (k, v) = cookie.split_once('=').ok_or("invalid cookie")?;
-                    cookies.insert(k[1..].to_string(), v.to_string());
-                }
-            } else {
-                let mut value = value.to_lowercase();
-                value.retain(|c| !c.is_whitespace());
-                headers.insert(key.to_lowercase(), value);
+    /// Whether the connection should stay open after this request is answered. HTTP/1.1
+    /// connections persist unless the client sends `Connection: close`, while HTTP/1.0 clients
+    /// have to opt in with `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        let has = |option| {
+            self.headers
+                .get("connection")
+                .is_some_and(|c| c.split(',').any(|o| o.trim().eq_ignore_ascii_case(option)))
+        };
+        match self.version {
+            Version::Http10 => has("keep-alive"),
+            Version::Http11 => !has("close"),
+        }
+    }
+
+    /// Reads the next request from `stream`, accumulating until the end of the headers and then
+    /// reading a body delimited by either `Content-Length` or chunked transfer-encoding. Multipart
+    /// bodies are parsed as they arrive rather than buffered. Bytes past the end of the request
+    /// are left in `buf` for the next call. Returns `Ok(None)` if the stream closes, or stays
+    /// idle for [`Config::keep_alive_timeout`], before a request starts. Once one has started it
+    /// has [`Config::read_timeout`] to arrive in full. Clients that send `Expect: 100-continue`
+    /// are told to go ahead with the body once its headers are accepted.
+    pub(crate) async fn read<R>(
+        stream: &mut R,
+        buf: &mut Vec<u8>,
+        config: &Config,
+    ) -> Result<Option<Self>, ParseError>
+    where
+        R: AsyncRead + AsyncWrite + Unpin,
+    {
+        if buf.is_empty() {
+            match timeout(config.keep_alive_timeout, fill(stream, buf)).await {
+                Ok(Ok(0)) | Err(_) => return Ok(None),
+                Ok(result) => result?,
+            };
+        }
+        timeout(config.read_timeout, Self::read_started(stream, buf, config))
+            .await
+            .map_err(|_| ParseError::Timeout)?
+    }
+
+    async fn read_started<R>(
+        stream: &mut R,
+        buf: &mut Vec<u8>,
+        config: &Config,
+    ) -> Result<Option<Self>, ParseError>
+    where
+        R: AsyncRead + AsyncWrite + Unpin,
+    {
+        let head_len = loop {
+            if let Some(end) = head_end(buf) {
+                break end;
+            }
+            if buf.len() > config.max_header_size {
+                // Still no end to the start-line means the request-target is what's too long
+                return Err(match buf.contains(&b'\n') {
+                    true => ParseError::HeadersTooLarge,
+                    false => ParseError::UriTooLong,
+                });
             }
+            if fill(stream, buf).await? == 0 {
+                return match buf.is_empty() {
+                    true => Ok(None),
+                    false => Err(ParseError::Incomplete),
+                };
+            }
+        };
+        if head_len > config.max_header_size {
+            return Err(ParseError::HeadersTooLarge);
+        }
+
+        let head = decode_head(buf.drain(..head_len).collect())?;
+        let target = head.lines().next().and_then(|l| l.split(' ').nth(1));
+        if target.is_some_and(|t| t.len() > config.max_uri_length) {
+            return Err(ParseError::UriTooLong);
         }
 
-        let body = if let Some("application/json") = headers.get("content-type").map(|s| &s[..]) {
-            let json: String = lines.collect();
-            let end = json.find('}').ok_or("invalid body")?;
-            match serde_json::from_str(&json[..end + 1]) {
-                Ok(body) => body,
-                Err(_) => return Err("invalid body"),
+        let chunked = is_chunked(&head)?;
+        let body_len = match (chunked, content_length(&head)?) {
+            // Whichever one a proxy in front trusted, the other could smuggle a second request
+            (true, Some(_)) => return Err(ParseError::ConflictingLength),
+            (true, None) => None,
+            (false, len) => Some(len.unwrap_or(0)),
+        };
+        let boundary = match body_len {
+            Some(0) => None,
+            _ => header(&head, "content-type").and_then(multipart::boundary),
+        };
+        let (mut body, limit) = match boundary {
+            Some(boundary) => (
+                Body::Multipart(Box::new(Parser::new(boundary, config))),
+                config.max_multipart_size,
+            ),
+            None => (Body::Raw(Vec::new()), config.max_body_size),
+        };
+        if body_len.is_some_and(|len| len > limit) {
+            return Err(ParseError::BodyTooLarge);
+        }
+        // There's nothing to wait for if the body is empty or the client sent it regardless
+        if expects_continue(&head) && buf.is_empty() && body_len != Some(0) {
+            stream
+                .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                .await
+                .map_err(|_| ParseError::ConnectionError)?;
+            stream
+                .flush()
+                .await
+                .map_err(|_| ParseError::ConnectionError)?;
+        }
+
+        let trailers = match body_len {
+            None => read_chunked(stream, buf, &mut body, limit, config.max_header_size).await?,
+            Some(len) => {
+                read_exact(stream, buf, &mut body, len).await?;
+                HeaderMap::new()
+            }
+        };
+
+        let mut request = match body {
+            Body::Raw(raw) => Self::from_parts(&head, raw)?,
+            Body::Multipart(parser) => {
+                let mut request = Self::from_parts(&head, Vec::new())?;
+                // An empty chunked body has no parts, not even a closing delimiter
+                if !parser.is_empty() {
+                    request.multipart = Some(parser.finish()?);
+                }
+                request
             }
-        } else {
-            Value::Null
         };
+        request.trailers = trailers;
+        Ok(Some(request))
+    }
+
+    /// Builds a request from its start-line and headers, which may be followed by blank lines,
+    /// and its already-delimited body.
+    fn from_parts(head: &str, raw_body: Vec<u8>) -> Result<Self, ParseError> {
+        let mut lines = head.lines();
+        let mut start_line = lines.next().ok_or(ParseError::EmptyRequest)?.split(' ');
+        let method = start_line.next().ok_or(ParseError::InvalidStartLine)?;
+        let target = start_line.next().ok_or(ParseError::InvalidStartLine)?;
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let version = start_line
+            .next()
+            .map_or(Ok(Version::Http11), Version::try_from)?;
 
-        Ok(Request {
+        let mut headers = HeaderMap::new();
+        let mut cookies = HashMap::new();
+
+        for line in lines.filter(|line| !line.is_empty()) {
+            let (key, value) = field(line).ok_or(ParseError::InvalidHeader)?;
+            if key.eq_ignore_ascii_case("cookie") {
+                for cookie in value.split(';') {
+                    let (k, v) = cookie.split_once('=').ok_or(ParseError::InvalidCookie)?;
+                    cookies.insert(k.trim().to_string(), v.trim().to_string());
+                }
+            }
+            headers.append(key, value);
+        }
+
+        let mut request = Request {
             method: method.try_into()?,
-            route: route.into(),
+            target: target.to_string(),
+            route: Route::decode(path).ok_or(ParseError::InvalidPercentEncoding)?,
+            version,
             params: HashMap::new(),
+            query: MultiMap::parse(query).ok_or(ParseError::InvalidPercentEncoding)?,
             headers,
+            trailers: HeaderMap::new(),
             cookies,
-            body,
+            multipart: None,
+            raw_body,
+            state: Arc::default(),
+        };
+        if request.media_type().as_deref() == Some("multipart/form-data")
+            && !request.raw_body.is_empty()
+        {
+            let boundary = request
+                .headers
+                .get("content-type")
+                .and_then(multipart::boundary)
+                .ok_or(ParseError::InvalidBody)?;
+            // The body is already in memory, so there's nothing to gain from moving parts to disk
+            let config = Config::default().max_part_memory(usize::MAX);
+            let mut parser = Parser::new(boundary, &config);
+            parser.feed(&request.raw_body)?;
+            request.multipart = Some(parser.finish()?);
+            request.raw_body.clear();
+        }
+        Ok(request)
+    }
+}
+
+/// Finds the index just past the blank line ending the headers, accepting bare `\n` line endings
+/// as well as `\r\n`.
+fn head_end(buf: &[u8]) -> Option<usize> {
+    buf.iter()
+        .enumerate()
+        .find_map(|(i, b)| match (b, &buf[i + 1..]) {
+            (b'\n', [b'\n', ..]) => Some(i + 2),
+            (b'\n', [b'\r', b'\n', ..]) => Some(i + 3),
+            _ => None,
         })
+}
+
+/// Decodes the start-line and headers. The start-line has to be UTF-8, but header values may
+/// carry obs-text, so a line that isn't UTF-8 is decoded as ISO-8859-1 instead, one char per byte,
+/// which keeps its original bytes recoverable.
+fn decode_head(head: Vec<u8>) -> Result<String, ParseError> {
+    let head = match String::from_utf8(head) {
+        Ok(head) => return Ok(head),
+        Err(e) => e.into_bytes(),
+    };
+    let mut lines = head.split(|b| b == &b'\n');
+    let start_line = lines.next().unwrap_or_default();
+    let mut decoded =
+        String::from_utf8(start_line.to_vec()).map_err(|_| ParseError::InvalidEncoding)?;
+    for line in lines {
+        decoded.push('\n');
+        match std::str::from_utf8(line) {
+            Ok(line) => decoded.push_str(line),
+            Err(_) => decoded.extend(line.iter().map(|b| *b as char)),
+        }
+    }
+    Ok(decoded)
+}
+
+/// Splits a header or trailer line into its name and value, if it's well-formed.
+fn field(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once(':')?;
+    match key.is_empty() || key.contains(char::is_whitespace) {
+        true => None,
+        false => Some((key, value)),
+    }
+}
+
+fn header<'a>(head: &'a str, name: &'a str) -> Option<&'a str> {
+    header_values(head, name).next()
+}
+
+/// Every value of `name` in `head`, in order.
+fn header_values<'a>(head: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> {
+    head.lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(move |(k, _)| k.trim().eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.trim_matches([' ', '\t']))
+}
+
+/// The body length given by `Content-Length`, which may be repeated or listed as long as every
+/// value agrees. Anything but plain digits is refused, since a proxy that reads a lenient value
+/// differently would see a different request boundary.
+fn content_length(head: &str) -> Result<Option<usize>, ParseError> {
+    let mut length = None;
+    for value in header_values(head, "content-length").flat_map(|v| v.split(',')) {
+        let value = value.trim_matches([' ', '\t']);
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseError::InvalidContentLength);
+        }
+        let value = value
+            .parse()
+            .map_err(|_| ParseError::InvalidContentLength)?;
+        if length.is_some_and(|length| length != value) {
+            return Err(ParseError::InvalidContentLength);
+        }
+        length = Some(value);
+    }
+    Ok(length)
+}
+
+/// Whether an HTTP/1.1 client is waiting for `100 Continue` before it sends the body.
+fn expects_continue(head: &str) -> bool {
+    let http11 = head
+        .lines()
+        .next()
+        .is_some_and(|l| l.ends_with(" HTTP/1.1"));
+    http11 && header(head, "expect").is_some_and(|v| v.eq_ignore_ascii_case("100-continue"))
+}
+
+/// Transfer-encoding takes precedence over `Content-Length`, and the only coding understood is
+/// `chunked`, which has to come last.
+fn is_chunked(head: &str) -> Result<bool, ParseError> {
+    match header(head, "transfer-encoding") {
+        None => Ok(false),
+        Some(v) => match v.rsplit(',').next().map(str::trim) {
+            Some(coding) if coding.eq_ignore_ascii_case("chunked") => Ok(true),
+            _ => Err(ParseError::UnsupportedTransferEncoding),
+        },
+    }
+}
+
+/// Where a body goes as it's read.
+enum Body {
+    Raw(Vec<u8>),
+    Multipart(Box<Parser>),
+}
+
+impl Body {
+    async fn push(&mut self, data: &[u8]) -> Result<(), ParseError> {
+        match self {
+            Body::Raw(raw) => {
+                raw.extend_from_slice(data);
+                Ok(())
+            }
+            Body::Multipart(parser) => {
+                parser.feed(data)?;
+                parser.flush().await
+            }
+        }
+    }
+}
+
+/// Moves the next `len` bytes of the stream into `body`.
+async fn read_exact<R>(
+    stream: &mut R,
+    buf: &mut Vec<u8>,
+    body: &mut Body,
+    len: usize,
+) -> Result<(), ParseError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut remaining = len;
+    while remaining > 0 {
+        if buf.is_empty() && fill(stream, buf).await? == 0 {
+            return Err(ParseError::Incomplete);
+        }
+        let n = remaining.min(buf.len());
+        body.push(&buf[..n]).await?;
+        buf.drain(..n);
+        remaining -= n;
+    }
+    Ok(())
+}
+
+/// Decodes a chunked body into `body`, returning any trailer fields.
+async fn read_chunked<R>(
+    stream: &mut R,
+    buf: &mut Vec<u8>,
+    body: &mut Body,
+    limit: usize,
+    max_header_size: usize,
+) -> Result<HeaderMap, ParseError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut len: usize = 0;
+    loop {
+        let line = read_line(stream, buf, max_header_size).await?;
+        let size = line.split(';').next().unwrap_or_default();
+        let size = size.trim_matches([' ', '\t']);
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseError::InvalidChunk);
+        }
+        let size = usize::from_str_radix(size, 16).map_err(|_| ParseError::BodyTooLarge)?;
+        if size == 0 {
+            break;
+        }
+        len = len
+            .checked_add(size)
+            .filter(|len| *len <= limit)
+            .ok_or(ParseError::BodyTooLarge)?;
+
+        read_exact(stream, buf, body, size).await?;
+        if !read_line(stream, buf, 2).await?.is_empty() {
+            return Err(ParseError::InvalidChunk);
+        }
+    }
+
+    let mut trailers = HeaderMap::new();
+    let mut size = 0;
+    loop {
+        let line = read_line(stream, buf, max_header_size.saturating_sub(size)).await?;
+        if line.is_empty() {
+            break;
+        }
+        size += line.len() + 2;
+        let (key, value) = field(&line).ok_or(ParseError::InvalidChunk)?;
+        trailers.append(key, value);
+    }
+    Ok(trailers)
+}
+
+/// Takes the next line off the front of `buf` without its line ending.
+async fn read_line<R>(stream: &mut R, buf: &mut Vec<u8>, limit: usize) -> Result<String, ParseError>
+where
+    R: AsyncRead + Unpin,
+{
+    let end = loop {
+        if let Some(i) = buf.iter().position(|b| b == &b'\n') {
+            break i;
+        }
+        if buf.len() > limit {
+            return Err(ParseError::InvalidChunk);
+        }
+        if fill(stream, buf).await? == 0 {
+            return Err(ParseError::Incomplete);
+        }
+    };
+    let line: Vec<u8> = buf.drain(..=end).collect();
+    let line = line[..end].strip_suffix(b"\r").unwrap_or(&line[..end]);
+    String::from_utf8(line.to_vec()).map_err(|_| ParseError::InvalidChunk)
+}
+
+async fn fill<R>(stream: &mut R, buf: &mut Vec<u8>) -> Result<usize, ParseError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut chunk = [0; 4096];
+    let n = stream
+        .read(&mut chunk)
+        .await
+        .map_err(|_| ParseError::ConnectionError)?;
+    buf.extend_from_slice(&chunk[..n]);
+    Ok(n)
+}
+
+impl TryFrom<String> for Request {
+    type Error = ParseError;
+
+    fn try_from(req: String) -> Result<Self, Self::Error> {
+        match head_end(req.as_bytes()) {
+            Some(end) => Self::from_parts(&req[..end], req[end..].into()),
+            None => Self::from_parts(&req, Vec::new()),
+        }
     }
 }
 
@@ -90,14 +620,24 @@ impl Debug for Request {
 mod tests {
     use super::*;
 
+    /// Reads from `stream`, discarding anything written back.
+    async fn read_from(
+        stream: &mut &[u8],
+        buf: &mut Vec<u8>,
+        config: &Config,
+    ) -> Result<Option<Request>, ParseError> {
+        Request::read(&mut tokio::io::join(stream, tokio::io::sink()), buf, config).await
+    }
+
     #[test]
     fn create() {
         let request = Request::try_from(String::from("GET /")).unwrap();
         assert_eq!(request.method, Method::GET);
         assert_eq!(request.route, Route { segments: vec![] });
 
-        let request = Request::try_from(String::from("DELETE /a/b/c/")).unwrap();
+        let request = Request::try_from(String::from("DELETE /a/b/c/?d=e")).unwrap();
         assert_eq!(request.method, Method::DELETE);
+        assert_eq!(request.target, "/a/b/c/?d=e");
         assert_eq!(
             request.route,
             Route {
@@ -124,10 +664,10 @@ host: localhost:3000
         );
         assert_eq!(
             request.headers.get("content-type"),
-            Some(&"application/json".to_string())
+            Some("application/json")
         );
-        assert_eq!(request.body["username"], Value::String("name".into()));
-        assert_eq!(request.body["age"], Value::Number(123.into()));
+        assert_eq!(request.body()["username"], Value::String("name".into()));
+        assert_eq!(request.body()["age"], Value::Number(123.into()));
 
         let request = Request::try_from(String::from(
             r#"GET / HTTP/1.1
@@ -140,45 +680,177 @@ cookie: key=value; another=hi"#,
         assert_eq!(request.cookies.get("another"), Some(&"hi".to_string()));
     }
 
+    #[test]
+    fn headers() {
+        let request = Request::try_from(String::from(
+            "GET / HTTP/1.1\r\n\
+             User-Agent: Mozilla/5.0 (X11; Linux)\r\n\
+             Authorization:  Bearer AbC \r\n\
+             If-None-Match: \"v1\", W/\"v2\"\r\n\
+             Accept: text/html\r\n\
+             accept: application/json\r\n\
+             Cookie: a=1\r\n\
+             Cookie: b=2\r\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            request.headers.get("user-agent"),
+            Some("Mozilla/5.0 (X11; Linux)")
+        );
+        assert_eq!(request.headers.get("authorization"), Some("Bearer AbC"));
+        assert_eq!(
+            request.headers.get("if-none-match"),
+            Some("\"v1\", W/\"v2\"")
+        );
+        assert_eq!(
+            request.headers.get_all("Accept").collect::<Vec<_>>(),
+            vec!["text/html", "application/json"]
+        );
+        assert_eq!(request.cookies.get("a"), Some(&"1".to_string()));
+        assert_eq!(request.cookies.get("b"), Some(&"2".to_string()));
+
+        let err = Request::try_from(String::from("GET / HTTP/1.1\nHost : a")).unwrap_err();
+        assert_eq!(err, ParseError::InvalidHeader);
+
+        let request = Request::try_from(String::from(
+            "POST / HTTP/1.1\nContent-Type: Application/JSON; charset=UTF-8\n\n{}",
+        ))
+        .unwrap();
+        assert!(request.is_json());
+    }
+
+    #[test]
+    fn query() {
+        let request =
+            Request::try_from(String::from("GET /search?q=rust%20web&tag=a&tag=b")).unwrap();
+        assert_eq!(request.route, Route::from("/search"));
+        assert_eq!(request.query.get("q"), Some("rust web"));
+        assert_eq!(
+            request.query.get_all("tag").collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+
+        let request = Request::try_from(String::from("GET /?")).unwrap();
+        assert_eq!(request.route, Route::from("/"));
+        assert!(request.query.is_empty());
+
+        let request = Request::try_from(String::from("GET /a%3Fb/../c?d")).unwrap();
+        assert_eq!(request.route, Route::from("/c"));
+        assert_eq!(request.query.get("d"), Some(""));
+
+        let err = Request::try_from(String::from("GET /%zz")).unwrap_err();
+        assert_eq!(err, ParseError::InvalidPercentEncoding);
+
+        let err = Request::try_from(String::from("GET /?q=%zz")).unwrap_err();
+        assert_eq!(err, ParseError::InvalidPercentEncoding);
+        assert_eq!(err.status(), StatusCode::BadRequest);
+    }
+
+    #[test]
+    fn json() {
+        let request = Request::try_from(String::from(
+            r#"POST / HTTP/1.1
+content-type: application/vnd.api+json; charset=utf-8
+
+{"users": [{"name": "}"}, {"name": "b"}]}"#,
+        ))
+        .unwrap();
+        assert_eq!(
+            request.body()["users"][0]["name"],
+            Value::String("}".into())
+        );
+        assert_eq!(
+            request.body()["users"][1]["name"],
+            Value::String("b".into())
+        );
+
+        #[derive(serde::Deserialize)]
+        struct User {
+            name: String,
+        }
+        let users: HashMap<String, Vec<User>> = request.json().unwrap();
+        assert_eq!(users["users"][0].name, "}");
+
+        let request = Request::try_from(String::from(
+            "POST / HTTP/1.1\ncontent-type: application/json\n\n[1, 2]",
+        ))
+        .unwrap();
+        assert_eq!(request.body(), Value::from(vec![1, 2]));
+        assert_eq!(request.raw_body, b"[1, 2]");
+
+        let request = Request::try_from(String::from(
+            "GET / HTTP/1.1\ncontent-type: application/json",
+        ))
+        .unwrap();
+        assert_eq!(request.body(), Value::Null);
+
+        // Invalid JSON is left for whoever reads the body to report
+        let request = Request::try_from(String::from(
+            "POST / HTTP/1.1\ncontent-type: application/json\n\n{\n    \"key\n}",
+        ))
+        .unwrap();
+        assert_eq!(request.body(), Value::Null);
+        assert!(request.json::<Value>().unwrap_err().is_syntax());
+        assert_eq!(request.raw_body, b"{\n    \"key\n}");
+    }
+
+    #[test]
+    fn form() {
+        let request = Request::try_from(String::from(
+            "POST / HTTP/1.1\ncontent-type: application/x-www-form-urlencoded; charset=UTF-8\n\nname=J+Doe&role=a&role=b%2Bc",
+        ))
+        .unwrap();
+        assert!(request.is_form());
+        let form = request.form().unwrap();
+        assert_eq!(form.get("name"), Some("J Doe"));
+        assert_eq!(form.get_all("role").collect::<Vec<_>>(), vec!["a", "b+c"]);
+        assert_eq!(request.body(), Value::Null);
+
+        let request = Request::try_from(String::from(
+            "POST / HTTP/1.1\ncontent-type: text/plain\n\nname=J+Doe",
+        ))
+        .unwrap();
+        assert!(!request.is_form());
+
+        // Invalid encoding is left for whoever reads the form to report
+        let request = Request::try_from(String::from(
+            "POST / HTTP/1.1\ncontent-type: application/x-www-form-urlencoded\n\nname=%",
+        ))
+        .unwrap();
+        assert!(request.is_form());
+        assert_eq!(request.form(), None);
+    }
+
     #[test]
     fn invalid_create() {
         match Request::try_from(String::new()) {
             Ok(_) => panic!("bad request didn't error"),
-            Err(e) => assert_eq!(e, "empty request"),
+            Err(e) => assert_eq!(e, ParseError::EmptyRequest),
         };
 
         match Request::try_from(String::from("OneLongWordWithNoSpaces")) {
             Ok(_) => panic!("bad request didn't error"),
-            Err(e) => assert_eq!(e, "invalid start-line"),
+            Err(e) => assert_eq!(e, ParseError::InvalidStartLine),
         };
 
         match Request::try_from(String::from("GWT /")) {
             Ok(_) => panic!("bad request didn't error"),
-            Err(e) => assert_eq!(e, "invalid method"),
+            Err(e) => assert_eq!(e, ParseError::InvalidMethod),
         };
 
-        match Request::try_from(String::from("GET / HTTP/1.1\nbad header")) {
+        match Request::try_from(String::from("GET / HTTP/2.0")) {
             Ok(_) => panic!("bad request didn't error"),
-            Err(e) => assert_eq!(e, "invalid header"),
+            Err(e) => assert_eq!(e, ParseError::UnsupportedVersion),
         };
 
-        match Request::try_from(String::from(
-            "GET / HTTP/1.1\ncontent-type:application/json\n\n[]",
-        )) {
+        match Request::try_from(String::from("GET / FTP")) {
             Ok(_) => panic!("bad request didn't error"),
-            Err(e) => assert_eq!(e, "invalid body"),
+            Err(e) => assert_eq!(e, ParseError::InvalidStartLine),
         };
 
-        match Request::try_from(String::from(
-            r#"GET / HTTP/1.1
-content-type:application/json
-
-{
-    "key
-}"#,
-        )) {
+        match Request::try_from(String::from("GET / HTTP/1.1\nbad header")) {
             Ok(_) => panic!("bad request didn't error"),
-            Err(e) => assert_eq!(e, "invalid body"),
+            Err(e) => assert_eq!(e, ParseError::InvalidHeader),
         };
 
         match Request::try_from(String::from(
@@ -186,10 +858,256 @@ content-type:application/json
 cookie: badcookie"#,
         )) {
             Ok(_) => panic!("bad request didn't error"),
-            Err(e) => assert_eq!(e, "invalid cookie"),
+            Err(e) => assert_eq!(e, ParseError::InvalidCookie),
         };
     }
 
+    #[tokio::test]
+    async fn read() {
+        let config = Config::default();
+        let body = format!(r#"{{"username": "{}"}}"#, "a".repeat(2000));
+        let raw = format!(
+            "POST / HTTP/1.1\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}GET /next",
+            body.len(),
+            body
+        );
+        let mut stream = raw.as_bytes();
+        let mut buf = Vec::new();
+
+        let request = read_from(&mut stream, &mut buf, &config)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.body()["username"], Value::String("a".repeat(2000)));
+        assert_eq!(buf, b"GET /next");
+
+        let mut stream: &[u8] = b"";
+        let mut buf = Vec::new();
+        assert!(read_from(&mut stream, &mut buf, &config)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn invalid_read() {
+        let config = Config::default().max_header_size(16).max_body_size(4);
+
+        let mut stream: &[u8] = b"GET / HTTP/1.1\r\nhost: localhost:3000\r\n\r\n";
+        let err = read_from(&mut stream, &mut Vec::new(), &config).await;
+        assert_eq!(err.unwrap_err(), ParseError::HeadersTooLarge);
+
+        let mut stream: &[u8] = b"GET /a/very/long/path HTTP/1.1";
+        let err = read_from(&mut stream, &mut Vec::new(), &config).await;
+        assert_eq!(err.unwrap_err(), ParseError::UriTooLong);
+
+        let mut stream: &[u8] = b"GET /\xff\n\n";
+        let err = read_from(&mut stream, &mut Vec::new(), &config).await;
+        assert_eq!(err.unwrap_err(), ParseError::InvalidEncoding);
+
+        // Header values may use obs-text, which is kept byte for byte
+        let mut stream: &[u8] = b"GET /\nx-name: caf\xe9\nx-utf8: caf\xc3\xa9\n\n";
+        let request = read_from(
+            &mut stream,
+            &mut Vec::new(),
+            &config.clone().max_header_size(64),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let value = request.headers.get("x-name").unwrap();
+        assert_eq!(value, "caf\u{e9}");
+        assert_eq!(
+            value.chars().map(|c| c as u8).collect::<Vec<_>>(),
+            b"caf\xe9"
+        );
+        assert_eq!(request.headers.get("x-utf8"), Some("café"));
+
+        let mut stream: &[u8] = b"POST /\ncontent-length: 5\n\nhello";
+        let config = config.max_header_size(1024).max_uri_length(8);
+        let err = read_from(&mut stream, &mut Vec::new(), &config).await;
+        assert_eq!(err.unwrap_err(), ParseError::BodyTooLarge);
+
+        let mut stream: &[u8] = b"GET /too/long/for/config\n\n";
+        let err = read_from(&mut stream, &mut Vec::new(), &config).await;
+        assert_eq!(err.unwrap_err(), ParseError::UriTooLong);
+
+        let mut stream: &[u8] = b"POST /\ncontent-length: 3\n\nhi";
+        let err = read_from(&mut stream, &mut Vec::new(), &config).await;
+        assert_eq!(err.unwrap_err(), ParseError::Incomplete);
+
+        for length in [
+            &b"many"[..],
+            b"+3",
+            b"-0",
+            b"0x3",
+            b"",
+            b"3\ncontent-length: 10",
+            b"3, 4",
+        ] {
+            let stream = [&b"POST /\ncontent-length: "[..], length, b"\n\nabc"].concat();
+            let err = read_from(&mut &stream[..], &mut Vec::new(), &config).await;
+            assert_eq!(err.unwrap_err(), ParseError::InvalidContentLength);
+        }
+
+        // Repeats that agree are only redundant
+        let mut stream: &[u8] = b"POST /\ncontent-length: 3\ncontent-length: 3, 3\n\nabc";
+        let request = read_from(&mut stream, &mut Vec::new(), &config).await;
+        assert_eq!(request.unwrap().unwrap().raw_body, b"abc");
+
+        let mut stream: &[u8] = b"POST /\ntransfer-encoding: gzip\n\n";
+        let err = read_from(&mut stream, &mut Vec::new(), &config).await;
+        assert_eq!(err.unwrap_err(), ParseError::UnsupportedTransferEncoding);
+
+        for size in ["zz", "+2", "-0", "0x2", ""] {
+            let stream = format!(
+                "POST /\ntransfer-encoding: chunked\n\n{}\r\nhi\r\n0\r\n\r\n",
+                size
+            );
+            let err = read_from(&mut stream.as_bytes(), &mut Vec::new(), &config).await;
+            assert_eq!(err.unwrap_err(), ParseError::InvalidChunk);
+        }
+
+        let mut stream: &[u8] =
+            b"POST /\ncontent-length: 5\ntransfer-encoding: chunked\n\n0\r\n\r\nhello";
+        let err = read_from(&mut stream, &mut Vec::new(), &config).await;
+        assert_eq!(err.unwrap_err(), ParseError::ConflictingLength);
+
+        let mut stream: &[u8] = b"POST /\ntransfer-encoding: chunked\n\n2\r\nhiya\r\n0\r\n\r\n";
+        let err = read_from(&mut stream, &mut Vec::new(), &config).await;
+        assert_eq!(err.unwrap_err(), ParseError::InvalidChunk);
+
+        let mut stream: &[u8] = b"POST /\ntransfer-encoding: chunked\n\n5\r\nhello\r\n0\r\n\r\n";
+        let err = read_from(&mut stream, &mut Vec::new(), &config).await;
+        assert_eq!(err.unwrap_err(), ParseError::BodyTooLarge);
+
+        let mut stream: &[u8] =
+            b"POST /\ntransfer-encoding: chunked\n\n1\r\na\r\nffffffffffffffff\r\n";
+        let err = read_from(&mut stream, &mut Vec::new(), &Config::default()).await;
+        assert_eq!(err.unwrap_err(), ParseError::BodyTooLarge);
+    }
+
+    #[tokio::test]
+    async fn read_chunked() {
+        let raw = "POST / HTTP/1.1\r\n\
+                   content-type: application/json\r\n\
+                   transfer-encoding: chunked\r\n\r\n\
+                   9;ext=1\r\n{\"name\": \r\n\
+                   a\r\n\"chunked\"}\r\n\
+                   0\r\n\
+                   x-checksum: abc\r\n\r\n\
+                   GET /next";
+        let mut stream = raw.as_bytes();
+        let mut buf = Vec::new();
+
+        let request = read_from(&mut stream, &mut buf, &Config::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.body()["name"], Value::String("chunked".into()));
+        assert_eq!(request.trailers.get("x-checksum"), Some("abc"));
+        assert!(!request.headers.contains("x-checksum"));
+        assert_eq!(buf, b"GET /next");
+
+        // A trailer can't change how the body is interpreted
+        let mut stream: &[u8] = b"POST /\ntransfer-encoding: chunked\n\n\
+                                  2\r\na=\r\n0\r\n\
+                                  content-type: application/x-www-form-urlencoded\r\n\r\n";
+        let request = read_from(&mut stream, &mut Vec::new(), &Config::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!request.is_form());
+        assert_eq!(request.raw_body, b"a=");
+        assert_eq!(
+            request.trailers.get("content-type"),
+            Some("application/x-www-form-urlencoded")
+        );
+    }
+
+    #[tokio::test]
+    async fn read_multipart() {
+        let body = "--b\r\n\
+                    Content-Disposition: form-data; name=\"note\"\r\n\r\n\
+                    hi\r\n\
+                    --b\r\n\
+                    Content-Disposition: form-data; name=\"doc\"; filename=\"doc.txt\"\r\n\r\n\
+                    0123456789\r\n\
+                    --b--";
+        let raw = format!(
+            "POST / HTTP/1.1\r\ncontent-type: multipart/form-data; boundary=b\r\ncontent-length: {}\r\n\r\n{}GET /next",
+            body.len(),
+            body
+        );
+        let config = Config::default().max_body_size(4).max_part_memory(4);
+
+        let mut stream = raw.as_bytes();
+        let mut buf = Vec::new();
+        let request = read_from(&mut stream, &mut buf, &config)
+            .await
+            .unwrap()
+            .unwrap();
+        let multipart = request.multipart.unwrap();
+        assert_eq!(multipart.field("note"), Some("hi"));
+        let doc = multipart.file("doc").unwrap();
+        assert!(doc.path().is_some());
+        assert_eq!(&doc.bytes().unwrap()[..], b"0123456789");
+        assert!(request.raw_body.is_empty());
+        assert_eq!(buf, b"GET /next");
+
+        let config = config.max_part_size(8);
+        let err = read_from(&mut raw.as_bytes(), &mut Vec::new(), &config).await;
+        assert_eq!(err.unwrap_err(), ParseError::BodyTooLarge);
+
+        let config = config.max_part_size(1024).max_multipart_size(64);
+        let err = read_from(&mut raw.as_bytes(), &mut Vec::new(), &config).await;
+        assert_eq!(err.unwrap_err(), ParseError::BodyTooLarge);
+
+        let request = Request::try_from(raw.replace("GET /next", "")).unwrap();
+        assert_eq!(request.multipart.unwrap().field("note"), Some("hi"));
+
+        let err = Request::try_from(raw.replace("--b--", "--b")).unwrap_err();
+        assert_eq!(err, ParseError::InvalidBody);
+
+        // Declaring a multipart type doesn't make an empty body one
+        for raw in [
+            &b"GET / HTTP/1.1\r\ncontent-type: multipart/form-data; boundary=x\r\n\r\n"[..],
+            b"POST / HTTP/1.1\r\ncontent-type: multipart/form-data; boundary=x\r\ntransfer-encoding: chunked\r\n\r\n0\r\n\r\n",
+        ] {
+            let request = read_from(&mut &raw[..], &mut Vec::new(), &Config::default())
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(request.multipart.is_none());
+        }
+    }
+
+    #[test]
+    fn error_status() {
+        assert_eq!(ParseError::InvalidCookie.status(), StatusCode::BadRequest);
+        assert_eq!(
+            ParseError::InvalidMethod.status(),
+            StatusCode::NotImplemented
+        );
+        assert_eq!(ParseError::UriTooLong.status(), StatusCode::URITooLong);
+        assert_eq!(
+            ParseError::HeadersTooLarge.status(),
+            StatusCode::RequestHeaderFieldsTooLarge
+        );
+        assert_eq!(ParseError::Timeout.status(), StatusCode::RequestTimeout);
+    }
+
+    #[test]
+    fn keep_alive() {
+        let request = |s: &str| Request::try_from(s.to_string()).unwrap();
+
+        assert!(request("GET / HTTP/1.1").keep_alive());
+        assert!(!request("GET / HTTP/1.1\nconnection: close").keep_alive());
+        assert!(!request("GET / HTTP/1.0").keep_alive());
+        assert!(request("GET / HTTP/1.0\nconnection: Keep-Alive").keep_alive());
+    }
+
     #[test]
     fn debug() {
         let debug = format!("{:?}", Request::try_from("POST /db".to_string()).unwrap());
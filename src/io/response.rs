@@ -1,40 +1,159 @@
-use std::{collections::HashMap, fs, path::Path};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use crate::{cookie::Cookie, io::status::Status, StatusCode};
+use httpdate::{fmt_http_date, parse_http_date};
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::{
+    io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+
+use crate::{
+    cookie::Cookie,
+    io::{mime, status::Status},
+    HeaderMap, StatusCode,
+};
+
+/// Sent as the `Server` header of responses that don't set their own.
+const SERVER: &str = concat!("web/", env!("CARGO_PKG_VERSION"));
+
+/// The page answered with when nothing else is found, relative to the working directory.
+const NOT_FOUND_PAGE: &str = "static/404.html";
+
+/// How many chunks a streamed body buffers before [`BodySender::send`] waits for the client.
+const STREAM_CAPACITY: usize = 16;
+
+/// How much of a file is read into each chunk of its streamed body.
+const FILE_CHUNK: usize = 64 * 1024;
+
+enum Body {
+    Full(Vec<u8>),
+
+    /// Chunks sent through a [`BodySender`], adding up to the length if it's known ahead of time.
+    Stream(mpsc::Receiver<Vec<u8>>, Option<u64>),
+}
+
+/// The sending half of a streamed response body. The body ends when this is dropped.
+pub struct BodySender(mpsc::Sender<Vec<u8>>);
+
+impl BodySender {
+    /// Sends the next chunk of the body, failing if the client has gone away.
+    pub async fn send(&self, chunk: impl Into<Vec<u8>>) -> Result<(), &'static str> {
+        self.0
+            .send(chunk.into())
+            .await
+            .map_err(|_| "connection closed")
+    }
+}
 
-#[derive(Clone)]
 pub struct Response {
-    content: String,
+    content: Body,
     status: Status,
-    headers: HashMap<String, String>,
+    headers: HeaderMap,
+    omit_body: bool,
+
+    /// The extension of the file served, from which its content-type is worked out.
+    extension: Option<String>,
 }
 
 impl Response {
     pub fn new() -> Self {
         Self {
-            content: String::new(),
+            content: Body::Full(Vec::new()),
             status: Status::from(StatusCode::OK),
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
+            omit_body: false,
+            extension: None,
         }
     }
 
+    /// Answers with the file at `path`, or the 404 page if it can't be read. Any path is read,
+    /// so use [`App::serve_dir`](crate::app::App::serve_dir) for paths that come from requests.
     pub fn serve_file<P>(mut self, path: P) -> Self
     where
         P: AsRef<Path>,
     {
-        self.content = match fs::read_to_string(path) {
-            Ok(content) => {
-                self.status = Status::from(StatusCode::OK);
-                content
-            }
+        let path = path.as_ref();
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(_) => return self.not_found(NOT_FOUND_PAGE),
+        };
+        self = self.file_headers(path, fs::metadata(path).ok());
+        self.content = Body::Full(content);
+        self
+    }
+
+    /// Like [`Response::serve_file`], but without blocking: the file is opened through
+    /// `tokio::fs` and streamed from a task a chunk at a time instead of being read into memory.
+    /// A file that can't be opened is answered with a plain 404 Not Found.
+    pub(crate) async fn stream_file(self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let file = match tokio::fs::File::open(path).await {
+            Ok(file) => file,
             Err(_) => {
-                self.status = Status::from(StatusCode::NotFound);
-                fs::read_to_string("static/404.html").unwrap()
+                let status = StatusCode::NotFound;
+                return self.status(status).content(status.to_string());
+            }
+        };
+        let metadata = file.metadata().await.ok();
+        let len = metadata.as_ref().map(fs::Metadata::len);
+        let mut res = self.file_headers(path, metadata);
+        let (sender, receiver) = mpsc::channel(STREAM_CAPACITY);
+        res.content = Body::Stream(receiver, len);
+        let sender = BodySender(sender);
+        tokio::spawn(async move {
+            let mut file = file;
+            let mut chunk = vec![0; FILE_CHUNK];
+            // Ends on a read error too, which the writer notices as a body shorter than its length
+            while let Ok(n @ 1..) = file.read(&mut chunk).await {
+                if sender.send(&chunk[..n]).await.is_err() {
+                    break;
+                }
             }
+        });
+        res
+    }
+
+    /// Sets the status, extension and validators of a response answering with the file at
+    /// `path`.
+    fn file_headers(mut self, path: &Path, metadata: Option<fs::Metadata>) -> Self {
+        self.status = Status::from(StatusCode::OK);
+        self.extension = path.extension().map(|e| e.to_string_lossy().into_owned());
+        let Some(metadata) = metadata else {
+            return self;
         };
+        if let Ok(modified) = metadata.modified() {
+            // Size and modification time change whenever the file is rewritten
+            let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+            self = self
+                .etag(format!("{:x}-{:x}", metadata.len(), since_epoch.as_nanos()))
+                .last_modified(modified);
+        }
         self
     }
 
+    /// Answers 404 Not Found with the HTML at `page`, or plain text if it can't be read.
+    fn not_found(mut self, page: impl AsRef<Path>) -> Self {
+        self.status = Status::from(StatusCode::NotFound);
+        match fs::read(page) {
+            Ok(content) => {
+                self.extension = Some(String::from("html"));
+                self.content = Body::Full(content);
+                self
+            }
+            Err(_) => {
+                self.extension = None;
+                self.content(StatusCode::NotFound.to_string())
+            }
+        }
+    }
+
     pub fn status(mut self, status: impl Into<Status>) -> Self {
         self.status = status.into();
         self
@@ -45,72 +164,691 @@ impl Response {
         Ok(self)
     }
 
-    pub fn content(mut self, content: String) -> Self {
-        self.content = content;
-        self
+    pub fn content(self, content: String) -> Self {
+        self.bytes(content)
     }
 
-    pub fn set_cookie(mut self, cookie: Cookie) -> Self {
-        self.headers
-            .insert(String::from("Set-Cookie"), cookie.as_header());
+    pub fn bytes(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.content = Body::Full(bytes.into());
         self
     }
 
-    pub fn delete_cookie(mut self, name: impl ToString) -> Self {
-        self.headers.insert(
-            String::from("Set-Cookie"),
+    /// Replaces the body with one produced incrementally through the returned [`BodySender`],
+    /// which is sent to the client with chunked transfer-encoding as chunks arrive.
+    pub fn stream(mut self) -> (BodySender, Self) {
+        let (sender, receiver) = mpsc::channel(STREAM_CAPACITY);
+        self.content = Body::Stream(receiver, None);
+        (BodySender(sender), self)
+    }
+
+    /// Adds a `Set-Cookie` header, keeping any cookies already set.
+    pub fn set_cookie(self, cookie: Cookie) -> Self {
+        self.append_header("Set-Cookie", cookie.as_header())
+    }
+
+    pub fn delete_cookie(self, name: impl ToString) -> Self {
+        self.append_header(
+            "Set-Cookie",
             format!(
                 "{}=; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
                 name.to_string()
             ),
-        );
+        )
+    }
+
+    /// Sets the `ETag` validator, which is quoted if it isn't already. GET and HEAD requests whose
+    /// `If-None-Match` lists it are answered with 304 Not Modified instead of the body.
+    pub fn etag(self, tag: impl ToString) -> Self {
+        let tag = tag.to_string();
+        if tag.ends_with('"') && (tag.starts_with('"') || tag.starts_with("W/\"")) {
+            self.header("ETag", tag)
+        } else {
+            self.header("ETag", format!("\"{}\"", tag))
+        }
+    }
+
+    /// Sets the `Last-Modified` validator. GET and HEAD requests whose `If-Modified-Since` is no
+    /// earlier are answered with 304 Not Modified instead of the body.
+    pub fn last_modified(self, time: SystemTime) -> Self {
+        self.header("Last-Modified", fmt_http_date(time))
+    }
+
+    /// Serializes `value` as the body with a JSON content-type, answering 500 Internal Server
+    /// Error instead if it can't be serialized.
+    pub fn json(self, value: &impl Serialize) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => self
+                .header("Content-Type", "application/json; charset=utf-8")
+                .bytes(body),
+            Err(e) => self
+                .status(StatusCode::InternalServerError)
+                .content(e.to_string()),
+        }
+    }
+
+    /// Sets the header `key`, replacing any values it already has.
+    pub fn header(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.headers.insert(key, value);
         self
     }
-}
 
-impl ToString for Response {
-    fn to_string(&self) -> String {
-        let status_line = format!("HTTP/1.1 {}", self.status);
-        let headers: String = self
-            .headers
-            .iter()
-            .map(|(k, v)| format!("\n{}: {}", k, v))
+    /// Adds another value for the header `key`, which is sent on its own line.
+    pub fn append_header(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.headers.append(key, value);
+        self
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Sets the content-type of a served file from `overrides`, keyed by lowercase extension,
+    /// unless the handler set one itself.
+    pub(crate) fn with_mime_types(self, overrides: &HashMap<String, String>) -> Self {
+        let content_type = match &self.extension {
+            _ if self.headers.contains("Content-Type") => None,
+            Some(extension) => overrides.get(&extension.to_ascii_lowercase()),
+            None => None,
+        };
+        match content_type {
+            Some(content_type) => {
+                let content_type = content_type.clone();
+                self.header("Content-Type", content_type)
+            }
+            None => self,
+        }
+    }
+
+    /// Turns a 200 response into 304 Not Modified, keeping its headers but not its body, if the
+    /// request's `If-None-Match` or, without one, `If-Modified-Since` shows the client already
+    /// has it.
+    pub(crate) fn check_conditions(self, request: &HeaderMap) -> Self {
+        if self.status != Status::from(StatusCode::OK) {
+            return self;
+        }
+        let tags: Vec<_> = request
+            .get_all("If-None-Match")
+            .flat_map(|tags| tags.split(','))
+            .map(str::trim)
             .collect();
-        format!(
-            "{}\r\nContent-Length: {}{}\r\n\r\n{}",
-            status_line,
-            self.content.len(),
-            headers,
-            self.content
+        let not_modified = if !tags.is_empty() {
+            // Weak comparison, since the body is the same either way for a GET
+            let weak = |tag: &str| tag.trim_start_matches("W/").to_string();
+            self.headers.get("ETag").is_some_and(|etag| {
+                tags.iter()
+                    .any(|tag| tag == &"*" || weak(tag) == weak(etag))
+            })
+        } else {
+            let since = request.get("If-Modified-Since").map(parse_http_date);
+            let modified = self.headers.get("Last-Modified").map(parse_http_date);
+            match (since, modified) {
+                (Some(Ok(since)), Some(Ok(modified))) => modified <= since,
+                _ => false,
+            }
+        };
+        match not_modified {
+            true => self.status(StatusCode::NotModified).without_body(),
+            false => self,
+        }
+    }
+
+    /// The request headers [`Response::check_conditions`] looks at, so they can be kept once the
+    /// request itself has gone to the handler.
+    pub(crate) fn conditions(request: &HeaderMap) -> HeaderMap {
+        let mut conditions = HeaderMap::new();
+        for name in ["If-None-Match", "If-Modified-Since"] {
+            for value in request.get_all(name) {
+                conditions.append(name, value);
+            }
+        }
+        conditions
+    }
+
+    /// Keeps the headers, including the length of the body, but doesn't send the body itself, as
+    /// in a response to a HEAD request.
+    pub(crate) fn without_body(mut self) -> Self {
+        self.omit_body = true;
+        self
+    }
+
+    pub(crate) fn connection(self, keep_alive: bool) -> Self {
+        self.header(
+            "Connection",
+            if keep_alive { "keep-alive" } else { "close" },
         )
     }
 }
 
+impl Response {
+    /// Writes the status-line and headers, adding `Date`, `Server` and `Content-Type` unless the
+    /// handler set them.
+    async fn write_head<W>(&self, w: &mut W, chunked: bool) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        w.write_all(format!("HTTP/1.1 {}\r\n", self.status).as_bytes())
+            .await?;
+        if !self.headers.contains("Date") {
+            write_header(w, "Date", &fmt_http_date(SystemTime::now())).await?;
+        }
+        if !self.headers.contains("Server") {
+            write_header(w, "Server", SERVER).await?;
+        }
+        if !self.headers.contains("Content-Type") {
+            if let Some(content_type) = self.default_content_type() {
+                write_header(w, "Content-Type", content_type).await?;
+            }
+        }
+        match &self.content {
+            _ if !self.status.allows_body() => {}
+            Body::Full(content) => {
+                write_header(w, "Content-Length", &content.len().to_string()).await?
+            }
+            Body::Stream(_, Some(len)) => {
+                write_header(w, "Content-Length", &len.to_string()).await?
+            }
+            Body::Stream(_, None) if chunked => {
+                write_header(w, "Transfer-Encoding", "chunked").await?
+            }
+            Body::Stream(_, None) => {}
+        }
+        for (name, value) in self.headers.iter() {
+            write_header(w, name, value).await?;
+        }
+        w.write_all(b"\r\n").await
+    }
+
+    /// The content-type of a body whose handler didn't set one. Files are looked up by their
+    /// extension. Other bodies are never sniffed for markup: UTF-8 is sent as plain text and
+    /// anything else as arbitrary bytes, so HTML has to be labelled by the handler. Streamed
+    /// bodies can't be inspected ahead of time so they're left for the client to work out.
+    fn default_content_type(&self) -> Option<&'static str> {
+        if !self.status.allows_body() {
+            return None;
+        }
+        if let Some(extension) = &self.extension {
+            return Some(mime::from_extension(extension).unwrap_or(mime::DEFAULT));
+        }
+        let content = match &self.content {
+            Body::Full(content) if !content.is_empty() => content,
+            _ => return None,
+        };
+        Some(match std::str::from_utf8(content) {
+            Ok(_) => "text/plain; charset=utf-8",
+            Err(_) => "application/octet-stream",
+        })
+    }
+
+    /// Writes the response to `w`. Streamed bodies are framed with chunked transfer-encoding
+    /// when `chunked` is set and otherwise written as-is, to be delimited by closing the
+    /// connection.
+    pub(crate) async fn write_to<W>(self, w: &mut W, chunked: bool) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.write_head(w, chunked).await?;
+        match self.content {
+            _ if self.omit_body || !self.status.allows_body() => {}
+            Body::Full(content) => w.write_all(&content).await?,
+            Body::Stream(mut receiver, Some(len)) => {
+                let mut left = len;
+                while let Some(chunk) = receiver.recv().await {
+                    let chunk = &chunk[..chunk.len().min(left as usize)];
+                    w.write_all(chunk).await?;
+                    w.flush().await?;
+                    left -= chunk.len() as u64;
+                }
+                // Anything else on the connection would be taken as the rest of the body
+                if left > 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            }
+            Body::Stream(mut receiver, None) => {
+                while let Some(chunk) = receiver.recv().await {
+                    if chunk.is_empty() {
+                        continue; // An empty chunk would end the body early
+                    }
+                    if chunked {
+                        w.write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+                            .await?;
+                        w.write_all(&chunk).await?;
+                        w.write_all(b"\r\n").await?;
+                    } else {
+                        w.write_all(&chunk).await?;
+                    }
+                    w.flush().await?;
+                }
+                if chunked {
+                    w.write_all(b"0\r\n\r\n").await?;
+                }
+            }
+        }
+        w.flush().await
+    }
+
+    /// Whether the body is streamed without a known length, so its end has to be marked by
+    /// chunked encoding or by closing the connection.
+    pub(crate) fn is_streamed(&self) -> bool {
+        matches!(self.content, Body::Stream(_, None))
+    }
+}
+
+async fn write_header<W>(w: &mut W, name: &str, value: &str) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    w.write_all(name.as_bytes()).await?;
+    w.write_all(b": ").await?;
+    w.write_all(value.as_bytes()).await?;
+    w.write_all(b"\r\n").await
+}
+
 impl Default for Response {
     fn default() -> Self {
+        Response::new().not_found(NOT_FOUND_PAGE)
+    }
+}
+
+/// Anything a handler can return. Strings become plain text bodies, JSON values become JSON
+/// bodies, a `(StatusCode, T)` sets the status of `T`'s response and a `Result` answers with
+/// whichever side it holds.
+pub trait IntoResponse {
+    fn into_response(self) -> Response;
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> Response {
+        self
+    }
+}
+
+impl IntoResponse for StatusCode {
+    fn into_response(self) -> Response {
+        Response::new().status(self)
+    }
+}
+
+impl IntoResponse for String {
+    fn into_response(self) -> Response {
         Response::new()
-            .serve_file("static/404.html")
-            .status(StatusCode::NotFound)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .content(self)
+    }
+}
+
+impl IntoResponse for &'static str {
+    fn into_response(self) -> Response {
+        self.to_string().into_response()
+    }
+}
+
+impl IntoResponse for Value {
+    fn into_response(self) -> Response {
+        Response::new().json(&self)
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for (StatusCode, T) {
+    fn into_response(self) -> Response {
+        self.1.into_response().status(self.0)
+    }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    fn into_response(self) -> Response {
+        match self {
+            Ok(res) => res.into_response(),
+            Err(res) => res.into_response(),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use serde_json::json;
+
     use super::*;
 
-    #[test]
-    fn format_response() {
+    /// Writes `res` with chunked encoding, checking and then removing the `Date` header so the
+    /// rest can be compared exactly.
+    async fn written(res: Response) -> Vec<u8> {
+        written_with(res, true).await
+    }
+
+    async fn written_with(res: Response, chunked: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        res.write_to(&mut out, chunked).await.unwrap();
+
+        let start = find(&out, b"\r\nDate: ").unwrap() + 2;
+        let end = start + find(&out[start..], b"\r\n").unwrap() + 2;
+        let date = std::str::from_utf8(&out[start + 6..end - 2]).unwrap();
+        assert!(httpdate::parse_http_date(date).is_ok());
+        out.drain(start..end);
+        out
+    }
+
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack.windows(needle.len()).position(|w| w == needle)
+    }
+
+    fn expected(head: &str, body: &[u8]) -> Vec<u8> {
+        let mut expected = head.replace("{server}", SERVER).into_bytes();
+        expected.extend_from_slice(body);
+        expected
+    }
+
+    #[tokio::test]
+    async fn format_response() {
         let content = String::from("hello");
-        let expected = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
-        assert_eq!(Response::new().content(content).to_string(), expected);
+        assert_eq!(
+            written(Response::new().content(content)).await,
+            expected(
+                "HTTP/1.1 200 OK\r\nServer: {server}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: 5\r\n\r\n",
+                b"hello"
+            )
+        );
+
+        assert_eq!(
+            written(Response::new().set_cookie(Cookie::new("key", "value"))).await,
+            expected(
+                "HTTP/1.1 200 OK\r\nServer: {server}\r\nContent-Length: 0\r\nSet-Cookie: key=value; SameSite=Lax\r\n\r\n",
+                b""
+            )
+        );
+
+        assert_eq!(
+            written(Response::new().bytes([0x89, b'P', b'N'])).await,
+            expected(
+                "HTTP/1.1 200 OK\r\nServer: {server}\r\nContent-Type: application/octet-stream\r\nContent-Length: 3\r\n\r\n",
+                b"\x89PN"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn default_headers() {
+        let res = Response::new().content(String::from("\n <!DOCTYPE html><p>hi"));
+        let res = String::from_utf8(written(res).await).unwrap();
+        assert!(res.contains("\r\nContent-Type: text/plain; charset=utf-8\r\n"));
+
+        let res = Response::new()
+            .header("Content-Type", "text/css")
+            .header("Server", "custom")
+            .header("Date", "Thu, 01 Jan 1970 00:00:00 GMT")
+            .content(String::from("p {}"));
+        let mut out = Vec::new();
+        res.write_to(&mut out, true).await.unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Length: 4\r\nContent-Type: text/css\r\nServer: custom\r\nDate: Thu, 01 Jan 1970 00:00:00 GMT\r\n\r\np {}"
+        );
+    }
+
+    #[tokio::test]
+    async fn not_found() {
+        let res = String::from_utf8(written(Response::default()).await).unwrap();
+        assert!(res.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(res.contains("\r\nContent-Type: text/html; charset=utf-8\r\n"));
+
+        let res = Response::new().not_found("static/missing.html");
+        assert_eq!(
+            written(res).await,
+            expected(
+                "HTTP/1.1 404 Not Found\r\nServer: {server}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: 9\r\n\r\n",
+                b"Not Found"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn file_content_type() {
+        let content_type = |res: Response| res.headers().get("Content-Type").map(String::from);
+
+        let res = Response::new().serve_file("Cargo.toml");
+        let res = String::from_utf8(written(res).await).unwrap();
+        assert!(res.contains("\r\nContent-Type: application/octet-stream\r\n"));
+
+        let res = Response::new().serve_file("static/404.html");
+        let res = String::from_utf8(written(res).await).unwrap();
+        assert!(res.contains("\r\nContent-Type: text/html; charset=utf-8\r\n"));
+
+        let mut overrides = HashMap::new();
+        overrides.insert(String::from("rs"), String::from("text/rust; charset=utf-8"));
+        let res = Response::new().serve_file("src/lib.rs");
+        assert_eq!(
+            content_type(res.with_mime_types(&overrides)).as_deref(),
+            Some("text/rust; charset=utf-8")
+        );
 
-        let expected =
-            "HTTP/1.1 200 OK\r\nContent-Length: 0\nSet-Cookie: key=value; SameSite=Lax\r\n\r\n";
+        let res = Response::new()
+            .serve_file("src/lib.rs")
+            .header("Content-Type", "text/plain");
         assert_eq!(
-            expected,
+            content_type(res.with_mime_types(&overrides)).as_deref(),
+            Some("text/plain")
+        );
+        let res = Response::new().content(String::from("fn main() {}"));
+        assert_eq!(content_type(res.with_mime_types(&overrides)), None);
+    }
+
+    #[test]
+    fn conditions() {
+        let request = |headers: &[(&str, &str)]| {
+            let mut map = HeaderMap::new();
+            for (k, v) in headers {
+                map.append(k, v);
+            }
+            map
+        };
+        let status = |res: Response| res.status.to_string();
+        let modified = UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        let cached = || {
             Response::new()
-                .set_cookie(Cookie::new("key", "value"))
-                .to_string()
+                .content(String::from("body"))
+                .etag("v2")
+                .last_modified(modified)
+        };
+        assert_eq!(cached().headers().get("ETag"), Some("\"v2\""));
+        assert_eq!(
+            Response::new().etag("W/\"v1\"").headers().get("etag"),
+            Some("W/\"v1\"")
+        );
+
+        let res = cached().check_conditions(&request(&[("If-None-Match", "\"v1\", W/\"v2\"")]));
+        assert_eq!(status(res), "304 Not Modified");
+        let res = cached().check_conditions(&request(&[("If-None-Match", "*")]));
+        assert_eq!(status(res), "304 Not Modified");
+        let res = cached().check_conditions(&request(&[("If-None-Match", "\"v1\"")]));
+        assert_eq!(status(res), "200 OK");
+
+        let since = fmt_http_date(modified);
+        let kept = Response::conditions(&request(&[
+            ("Authorization", "Bearer abc"),
+            ("if-modified-since", &since),
+        ]));
+        assert_eq!(kept.len(), 1);
+        let res = cached().check_conditions(&kept);
+        assert_eq!(status(res), "304 Not Modified");
+        let earlier = fmt_http_date(modified - std::time::Duration::from_secs(1));
+        let res = cached().check_conditions(&request(&[("If-Modified-Since", &earlier)]));
+        assert_eq!(status(res), "200 OK");
+
+        // If-None-Match takes precedence when both are sent
+        let res = cached().check_conditions(&request(&[
+            ("If-None-Match", "\"v1\""),
+            ("If-Modified-Since", &since),
+        ]));
+        assert_eq!(status(res), "200 OK");
+
+        let res = cached()
+            .status(StatusCode::Created)
+            .check_conditions(&request(&[("If-None-Match", "*")]));
+        assert_eq!(status(res), "201 Created");
+    }
+
+    #[tokio::test]
+    async fn not_modified() {
+        let res = Response::new().serve_file("static/404.html");
+        let etag = res.headers().get("ETag").unwrap().to_string();
+        assert!(res.headers().contains("Last-Modified"));
+
+        let mut request = HeaderMap::new();
+        request.append("If-None-Match", etag);
+        let res = String::from_utf8(written(res.check_conditions(&request)).await).unwrap();
+        assert!(res.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+        assert!(res.ends_with("\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn multiple_headers() {
+        let res = Response::new()
+            .set_cookie(Cookie::new("session", "abc"))
+            .set_cookie(Cookie::new("csrf", "xyz"))
+            .delete_cookie("old")
+            .append_header("Vary", "Accept")
+            .append_header("Vary", "Cookie");
+        assert_eq!(res.headers().get_all("set-cookie").count(), 3);
+
+        let res = String::from_utf8(written(res).await).unwrap();
+        assert!(res.contains("Set-Cookie: session=abc; SameSite=Lax"));
+        assert!(res.contains("Set-Cookie: csrf=xyz; SameSite=Lax"));
+        assert!(res.contains("Set-Cookie: old=; Expires=Thu, 01 Jan 1970 00:00:00 GMT"));
+        assert!(res.contains("Vary: Accept"));
+        assert!(res.contains("Vary: Cookie"));
+
+        let res = Response::new()
+            .append_header("Vary", "Accept")
+            .header("vary", "*");
+        assert_eq!(res.headers().get_all("Vary").collect::<Vec<_>>(), vec!["*"]);
+    }
+
+    #[tokio::test]
+    async fn header_injection() {
+        let res = Response::new().header("Location", "/a\r\nSet-Cookie: admin=1\r\n\r\n<script>");
+        let res = String::from_utf8(written(res).await).unwrap();
+        assert!(res.contains("\r\nLocation: /aSet-Cookie: admin=1<script>\r\n"));
+        assert!(!res.contains("\r\nSet-Cookie"));
+    }
+
+    #[tokio::test]
+    async fn without_body() {
+        let res = Response::new()
+            .content(String::from("hello"))
+            .without_body();
+        assert_eq!(
+            written(res).await,
+            expected(
+                "HTTP/1.1 200 OK\r\nServer: {server}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: 5\r\n\r\n",
+                b""
+            )
         );
     }
+
+    #[tokio::test]
+    async fn no_content() {
+        let res = Response::new()
+            .status(StatusCode::NoContent)
+            .content(String::from("ignored"));
+        assert_eq!(
+            written(res).await,
+            expected("HTTP/1.1 204 No Content\r\nServer: {server}\r\n\r\n", b"")
+        );
+
+        let res = Response::new().status(StatusCode::Continue);
+        assert_eq!(
+            written(res).await,
+            expected("HTTP/1.1 100 Continue\r\nServer: {server}\r\n\r\n", b"")
+        );
+    }
+
+    #[tokio::test]
+    async fn stream() {
+        let (sender, res) = Response::new().stream();
+        tokio::spawn(async move {
+            sender.send("hello, ").await.unwrap();
+            sender.send("").await.unwrap();
+            sender.send("world").await.unwrap();
+        });
+
+        assert_eq!(
+            written(res).await,
+            expected(
+                "HTTP/1.1 200 OK\r\nServer: {server}\r\nTransfer-Encoding: chunked\r\n\r\n",
+                b"7\r\nhello, \r\n5\r\nworld\r\n0\r\n\r\n"
+            )
+        );
+
+        let (sender, res) = Response::new().stream();
+        tokio::spawn(async move { sender.send("raw").await.unwrap() });
+
+        assert_eq!(
+            written_with(res, false).await,
+            expected("HTTP/1.1 200 OK\r\nServer: {server}\r\n\r\n", b"raw")
+        );
+
+        // A stream of known length is cut to it, and fails if it falls short
+        let sized = |len, chunks: &'static [&'static str]| {
+            let (sender, mut res) = Response::new().stream();
+            if let Body::Stream(receiver, None) = res.content {
+                res.content = Body::Stream(receiver, Some(len));
+            }
+            tokio::spawn(async move {
+                for chunk in chunks {
+                    sender.send(*chunk).await.unwrap();
+                }
+            });
+            res
+        };
+        assert_eq!(
+            written(sized(4, &["ab", "cdef"])).await,
+            expected(
+                "HTTP/1.1 200 OK\r\nServer: {server}\r\nContent-Length: 4\r\n\r\n",
+                b"abcd"
+            )
+        );
+        let mut out = Vec::new();
+        assert!(sized(4, &["ab"]).write_to(&mut out, true).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn json() {
+        #[derive(Serialize)]
+        struct User {
+            id: u32,
+            tags: Vec<&'static str>,
+        }
+        let user = User {
+            id: 1,
+            tags: vec!["a"],
+        };
+        let res = String::from_utf8(written(Response::new().json(&user)).await).unwrap();
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(res.contains("Content-Type: application/json; charset=utf-8"));
+        assert!(res.ends_with(r#"{"id":1,"tags":["a"]}"#));
+
+        let mut keys = HashMap::new();
+        keys.insert(vec![1], 1);
+        let res = String::from_utf8(written(Response::new().json(&keys)).await).unwrap();
+        assert!(res.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(!res.contains("application/json"));
+    }
+
+    #[tokio::test]
+    async fn into_response() {
+        let res = String::from_utf8(written("hi".into_response()).await).unwrap();
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(res.contains("Content-Type: text/plain; charset=utf-8"));
+        assert!(res.ends_with("\r\n\r\nhi"));
+
+        let res = (StatusCode::Created, json!({ "id": 1 })).into_response();
+        let res = String::from_utf8(written(res).await).unwrap();
+        assert!(res.starts_with("HTTP/1.1 201 Created\r\n"));
+        assert!(res.contains("Content-Type: application/json; charset=utf-8"));
+        assert!(res.ends_with(r#"{"id":1}"#));
+
+        let err: Result<String, _> = Err((StatusCode::NotFound, "missing"));
+        let res = String::from_utf8(written(err.into_response()).await).unwrap();
+        assert!(res.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(res.ends_with("missing"));
+    }
 }
@@ -35,13 +35,22 @@ impl From<StatusCode> for Status {
     }
 }
 
+impl Status {
+    /// Whether a response with this status may have a body, which informational and
+    /// 204 No Content responses can't.
+    pub(crate) fn allows_body(&self) -> bool {
+        !(100..200).contains(&self.num) && self.num != 204
+    }
+}
+
 impl std::fmt::Display for Status {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} {}", self.num, self.msg.to_string())
+        write!(f, "{} {}", self.num, self.msg)
     }
 }
 
 // Once again bad naming
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum StatusCode {
     Continue,
     SwitchingProtocol,
@@ -112,85 +121,77 @@ pub enum StatusCode {
     NetworkAuthenticationRequired,
 }
 
-impl ToString for StatusCode {
-    fn to_string(&self) -> String {
-        match self {
-            StatusCode::Continue => String::from("Continue"),
-            StatusCode::SwitchingProtocol => String::from("Switching Protocol"),
-            StatusCode::Processing => String::from("Processing"),
-            StatusCode::EarlyHints => String::from("Early Hints"),
+impl std::fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            StatusCode::Continue => "Continue",
+            StatusCode::SwitchingProtocol => "Switching Protocol",
+            StatusCode::Processing => "Processing",
+            StatusCode::EarlyHints => "Early Hints",
 
-            StatusCode::OK => String::from("OK"),
-            StatusCode::Created => String::from("Created"),
-            StatusCode::Accepted => String::from("Accepted"),
-            StatusCode::NonAuthoritativeInformation => {
-                String::from("Non-Authoritative Information")
-            }
-            StatusCode::NoContent => String::from("No Content"),
-            StatusCode::ResetContent => String::from("Reset Content"),
-            StatusCode::PartialContent => String::from("Partial Content"),
-            StatusCode::MultiStatus => String::from("Multi-Status"),
-            StatusCode::AlreadyReported => String::from("Already Resported"),
-            StatusCode::IMUsed => String::from("IM Used"),
+            StatusCode::OK => "OK",
+            StatusCode::Created => "Created",
+            StatusCode::Accepted => "Accepted",
+            StatusCode::NonAuthoritativeInformation => "Non-Authoritative Information",
+            StatusCode::NoContent => "No Content",
+            StatusCode::ResetContent => "Reset Content",
+            StatusCode::PartialContent => "Partial Content",
+            StatusCode::MultiStatus => "Multi-Status",
+            StatusCode::AlreadyReported => "Already Reported",
+            StatusCode::IMUsed => "IM Used",
 
-            StatusCode::MultipleChoice => String::from("Multiple Choice"),
-            StatusCode::MovedPermanently => String::from("Moved Permanently"),
-            StatusCode::Found => String::from("Found"),
-            StatusCode::SeeOther => String::from("See Other"),
-            StatusCode::NotModified => String::from("Not Modified"),
-            StatusCode::UseProxy => String::from("Use Proxy"),
-            StatusCode::Unused => String::from("unused"),
-            StatusCode::TemporaryRedirect => String::from("Temporary Redirect"),
-            StatusCode::PermanentRedirect => String::from("Permanent Redirect"),
+            StatusCode::MultipleChoice => "Multiple Choice",
+            StatusCode::MovedPermanently => "Moved Permanently",
+            StatusCode::Found => "Found",
+            StatusCode::SeeOther => "See Other",
+            StatusCode::NotModified => "Not Modified",
+            StatusCode::UseProxy => "Use Proxy",
+            StatusCode::Unused => "unused",
+            StatusCode::TemporaryRedirect => "Temporary Redirect",
+            StatusCode::PermanentRedirect => "Permanent Redirect",
 
-            StatusCode::BadRequest => String::from("Bad Request"),
-            StatusCode::Unauthorized => String::from("Unauthorized"),
-            StatusCode::PaymentRequired => String::from("Payment Required"),
-            StatusCode::Forbidden => String::from("Forbidden"),
-            StatusCode::NotFound => String::from("Not Found"),
-            StatusCode::MethodNotAllowed => String::from("Method Not Allowed"),
-            StatusCode::NotAcceptable => String::from("Not Acceptable"),
-            StatusCode::ProxyAuthenticationRequired => {
-                String::from("Proxy Authentication Required")
-            }
-            StatusCode::RequestTimeout => String::from("Request Timeout"),
-            StatusCode::Conflict => String::from("Conflict"),
-            StatusCode::Gone => String::from("Gone"),
-            StatusCode::LengthRequired => String::from("Length Required"),
-            StatusCode::PreconditionFailed => String::from("Precondition Failed"),
-            StatusCode::PayloadTooLarge => String::from("Payload Too Large"),
-            StatusCode::URITooLong => String::from("URI Too Long"),
-            StatusCode::UnsupportedMediaType => String::from("Unsupported Media Type"),
-            StatusCode::RangeNotSatisfiable => String::from("Range Not Satisfiable"),
-            StatusCode::ExpectationFailed => String::from("Expectation Failed"),
-            StatusCode::ImATeapot => String::from("I'm a teapot"),
-            StatusCode::MisdirectedRequest => String::from("Misdirection Request"),
-            StatusCode::UnprocessableEntity => String::from("Unprocessalbe Entity"),
-            StatusCode::Locked => String::from("Locked"),
-            StatusCode::FailedDependency => String::from("Failed Dependency"),
-            StatusCode::TooEarly => String::from("Too Early"),
-            StatusCode::UpgradeRequired => String::from("Upgrade Required"),
-            StatusCode::PreconditionRequired => String::from("Precondition Required"),
-            StatusCode::TooManyRequests => String::from("Too Many Requests"),
-            StatusCode::RequestHeaderFieldsTooLarge => {
-                String::from("Request Header Fields Too Large")
-            }
-            StatusCode::UnavailableForLegalReasons => String::from("Unavailable For Legal Reasons"),
+            StatusCode::BadRequest => "Bad Request",
+            StatusCode::Unauthorized => "Unauthorized",
+            StatusCode::PaymentRequired => "Payment Required",
+            StatusCode::Forbidden => "Forbidden",
+            StatusCode::NotFound => "Not Found",
+            StatusCode::MethodNotAllowed => "Method Not Allowed",
+            StatusCode::NotAcceptable => "Not Acceptable",
+            StatusCode::ProxyAuthenticationRequired => "Proxy Authentication Required",
+            StatusCode::RequestTimeout => "Request Timeout",
+            StatusCode::Conflict => "Conflict",
+            StatusCode::Gone => "Gone",
+            StatusCode::LengthRequired => "Length Required",
+            StatusCode::PreconditionFailed => "Precondition Failed",
+            StatusCode::PayloadTooLarge => "Payload Too Large",
+            StatusCode::URITooLong => "URI Too Long",
+            StatusCode::UnsupportedMediaType => "Unsupported Media Type",
+            StatusCode::RangeNotSatisfiable => "Range Not Satisfiable",
+            StatusCode::ExpectationFailed => "Expectation Failed",
+            StatusCode::ImATeapot => "I'm a teapot",
+            StatusCode::MisdirectedRequest => "Misdirected Request",
+            StatusCode::UnprocessableEntity => "Unprocessable Entity",
+            StatusCode::Locked => "Locked",
+            StatusCode::FailedDependency => "Failed Dependency",
+            StatusCode::TooEarly => "Too Early",
+            StatusCode::UpgradeRequired => "Upgrade Required",
+            StatusCode::PreconditionRequired => "Precondition Required",
+            StatusCode::TooManyRequests => "Too Many Requests",
+            StatusCode::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
+            StatusCode::UnavailableForLegalReasons => "Unavailable For Legal Reasons",
 
-            StatusCode::InternalServerError => String::from("Internal Server Error"),
-            StatusCode::NotImplemented => String::from("Not Implemented"),
-            StatusCode::BadGateway => String::from("Bad Gateway"),
-            StatusCode::ServiceUnavailable => String::from("Service Unavailable"),
-            StatusCode::GatewayTimeout => String::from("Gateway Timeout"),
-            StatusCode::HTTPVersionNotSupported => String::from("HTTP Version Not Supported"),
-            StatusCode::VariantAlsoNegotiates => String::from("Variant Also Negotiates"),
-            StatusCode::InsufficientStorage => String::from("Insufficient Storage"),
-            StatusCode::LoopDetected => String::from("Loop Detected"),
-            StatusCode::NotExtended => String::from("Not Extended"),
-            StatusCode::NetworkAuthenticationRequired => {
-                String::from("Network Authentication Required")
-            }
-        }
+            StatusCode::InternalServerError => "Internal Server Error",
+            StatusCode::NotImplemented => "Not Implemented",
+            StatusCode::BadGateway => "Bad Gateway",
+            StatusCode::ServiceUnavailable => "Service Unavailable",
+            StatusCode::GatewayTimeout => "Gateway Timeout",
+            StatusCode::HTTPVersionNotSupported => "HTTP Version Not Supported",
+            StatusCode::VariantAlsoNegotiates => "Variant Also Negotiates",
+            StatusCode::InsufficientStorage => "Insufficient Storage",
+            StatusCode::LoopDetected => "Loop Detected",
+            StatusCode::NotExtended => "Not Extended",
+            StatusCode::NetworkAuthenticationRequired => "Network Authentication Required",
+        })
     }
 }
 
@@ -277,6 +278,17 @@ mod tests {
 
         assert_eq!(expected, Status::try_from(200).unwrap());
         assert_eq!(expected, Status::from(StatusCode::OK));
+
+        for code in [
+            StatusCode::AlreadyReported,
+            StatusCode::MisdirectedRequest,
+            StatusCode::UnprocessableEntity,
+        ] {
+            assert_eq!(
+                Status::try_from(Status::from(code).num).unwrap().msg,
+                code.to_string()
+            );
+        }
     }
 
     #[test]
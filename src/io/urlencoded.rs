@@ -0,0 +1,138 @@
+use serde::de::DeserializeOwned;
+
+/// Key-value pairs decoded from an `application/x-www-form-urlencoded` string, such as a query
+/// string. Keys may repeat and pairs keep the order they were sent in.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct MultiMap(Vec<(String, String)>);
+
+impl MultiMap {
+    /// Decodes `s`, treating `+` as a space. Returns `None` if a pair isn't valid UTF-8 once
+    /// percent-decoded.
+    pub fn parse(s: &str) -> Option<Self> {
+        s.split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                let decode = |s: &str| percent_decode(&s.replace('+', " "));
+                Some((decode(key)?, decode(value)?))
+            })
+            .collect::<Option<_>>()
+            .map(MultiMap)
+    }
+
+    /// The first value sent for `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| &v[..])
+    }
+
+    /// Every value sent for `key`, in order.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> {
+        self.0
+            .iter()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, v)| &v[..])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (&k[..], &v[..]))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Deserializes the pairs into `T`, which should be a struct or map. A key that repeats is an
+    /// error for a struct field, while a map keeps the last value.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, serde_urlencoded::de::Error> {
+        // Values are strings here, so go back through the urlencoded deserializer which knows
+        // how to parse them into numbers and the like
+        let encoded = serde_urlencoded::to_string(&self.0).unwrap_or_default();
+        serde_urlencoded::from_str(&encoded)
+    }
+}
+
+impl<K: ToString, V: ToString> FromIterator<(K, V)> for MultiMap {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(pairs: I) -> Self {
+        MultiMap(
+            pairs
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+}
+
+/// Decodes `%XX` escapes in `s`, returning `None` if an escape is malformed or the result isn't
+/// UTF-8.
+pub(crate) fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = s.bytes();
+    let mut decoded = Vec::with_capacity(s.len());
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+            decoded.push(hex(bytes.next()?)? << 4 | hex(bytes.next()?)?);
+        } else {
+            decoded.push(b);
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn decode() {
+        assert_eq!(percent_decode("a%20b%2Fc"), Some(String::from("a b/c")));
+        assert_eq!(percent_decode("caf%C3%A9"), Some(String::from("café")));
+        assert_eq!(percent_decode("a+b"), Some(String::from("a+b")));
+        assert_eq!(percent_decode("%2"), None);
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%+1"), None);
+        assert_eq!(percent_decode("%ff"), None);
+    }
+
+    #[test]
+    fn parse() {
+        let map = MultiMap::parse("q=rust+web&tag=a&tag=b%26c&flag&&empty=").unwrap();
+        assert_eq!(map.get("q"), Some("rust web"));
+        assert_eq!(map.get("tag"), Some("a"));
+        assert_eq!(map.get_all("tag").collect::<Vec<_>>(), vec!["a", "b&c"]);
+        assert_eq!(map.get("flag"), Some(""));
+        assert_eq!(map.get("empty"), Some(""));
+        assert_eq!(map.get("missing"), None);
+        assert_eq!(map.iter().count(), 5);
+
+        assert!(MultiMap::parse("").unwrap().is_empty());
+        assert_eq!(MultiMap::parse("a=%"), None);
+    }
+
+    #[test]
+    fn deserialize() {
+        #[derive(serde::Deserialize)]
+        struct Search {
+            q: String,
+            page: u32,
+        }
+
+        let map = MultiMap::parse("q=a%26b&page=2").unwrap();
+        let search: Search = map.deserialize().unwrap();
+        assert_eq!(search.q, "a&b");
+        assert_eq!(search.page, 2);
+
+        let map: MultiMap = [("q", "a b"), ("page", "3")].into_iter().collect();
+        let search: Search = map.deserialize().unwrap();
+        assert_eq!(search.q, "a b");
+        assert_eq!(search.page, 3);
+
+        let map = MultiMap::parse("q=a&page=two").unwrap();
+        assert!(map.deserialize::<Search>().is_err());
+
+        let map = MultiMap::parse("a=1&a=2").unwrap();
+        let map: HashMap<String, String> = map.deserialize().unwrap();
+        assert_eq!(map["a"], "2");
+    }
+}
@@ -1,9 +1,13 @@
 pub mod app;
 pub mod cookie;
+pub mod extract;
+pub mod handler;
 pub mod io;
 mod route;
+pub mod static_files;
 
+pub use io::headers::HeaderMap;
 pub use io::request::Request;
-pub use io::response::Response;
+pub use io::response::{IntoResponse, Response};
 pub use io::status::StatusCode;
 pub use macros::main;
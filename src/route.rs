@@ -1,25 +1,18 @@
-use std::{collections::HashMap, fmt::Debug};
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
 
 use regex::Regex;
 
-use crate::Request;
+use crate::{
+    app::{Logger, Method},
+    handler::Handler,
+    io::urlencoded::percent_decode,
+};
 
-#[derive(Eq, Clone)]
+#[derive(PartialEq, Eq, Clone)]
 pub struct Route {
     pub(crate) segments: Vec<String>,
 }
 
-impl Route {
-    pub fn params(&self, req: &Request) -> HashMap<String, String> {
-        self.segments
-            .iter()
-            .zip(&req.route.segments)
-            .filter(|(s, _)| s.starts_with(':'))
-            .map(|(s, o)| (s[1..].to_owned(), o.clone()))
-            .collect()
-    }
-}
-
 impl<T: ToString> From<T> for Route {
     fn from(route: T) -> Self {
         Route {
@@ -33,18 +26,22 @@ impl<T: ToString> From<T> for Route {
     }
 }
 
-impl PartialEq for Route {
-    fn eq(&self, other: &Self) -> bool {
-        self.segments.len() == other.segments.len()
-            && self.segments.iter().zip(&other.segments).all(|(a, b)| {
-                if a.chars().all(char::is_alphanumeric) {
-                    a == b
-                } else if a.starts_with(':') {
-                    true // match on all parameters
-                } else {
-                    Regex::new(a).unwrap().is_match(b)
+impl Route {
+    /// Parses the path of a request-target, percent-decoding each segment after splitting so an
+    /// encoded `/` stays inside its segment, and resolving `.` and `..` segments as RFC 3986
+    /// describes. Returns `None` if a segment isn't validly encoded.
+    pub(crate) fn decode(path: &str) -> Option<Self> {
+        let mut segments = Vec::new();
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            match &percent_decode(segment)?[..] {
+                "." => {}
+                ".." => {
+                    segments.pop();
                 }
-            })
+                segment => segments.push(segment.to_owned()),
+            }
+        }
+        Some(Route { segments })
     }
 }
 
@@ -54,49 +51,403 @@ impl Debug for Route {
     }
 }
 
+pub(crate) struct Endpoint {
+    pub(crate) method: Method,
+    pub(crate) route: Route,
+    pub(crate) handler: Box<dyn Handler>,
+    pub(crate) logger: Option<Arc<Logger>>,
+}
+
+/// One segment of the route tree. A request segment is matched against the static children
+/// first, then the param, then each regex in the order they were registered, backtracking to the
+/// next candidate if the rest of the path doesn't match below it. A `*name` wildcard matches
+/// whatever is left of the path, including nothing, when nothing more specific does.
+///
+/// Segments are static unless they use regex syntax other than `.`, `-` and `_`, so `robots.txt`
+/// and `about-us` match only themselves. Regex segments have to match the whole request segment,
+/// as described on [`App`](crate::app::App).
+#[derive(Default)]
+struct Node {
+    statics: HashMap<String, Node>,
+    param: Option<(String, Box<Node>)>,
+    regexes: Vec<(Regex, Node)>,
+    wildcard: Option<(String, Box<Node>)>,
+    endpoints: Vec<Endpoint>,
+}
+
+impl Node {
+    /// The methods routed here, including the HEAD and OPTIONS requests that are answered
+    /// automatically.
+    fn allowed(&self) -> Vec<Method> {
+        let mut allowed: Vec<_> = self.endpoints.iter().map(|e| e.method.clone()).collect();
+        if allowed.contains(&Method::GET) && !allowed.contains(&Method::HEAD) {
+            allowed.push(Method::HEAD);
+        }
+        if !allowed.contains(&Method::OPTIONS) {
+            allowed.push(Method::OPTIONS);
+        }
+        allowed
+    }
+
+    fn child(&mut self, segment: &str, route: &Route) -> &mut Node {
+        if let Some(name) = segment.strip_prefix(':') {
+            named(&mut self.param, ':', name, route)
+        } else if let Some(name) = segment.strip_prefix('*') {
+            if route.segments.last().map(String::as_str) != Some(segment) {
+                panic!("route {:?} has a wildcard before its last segment", route);
+            }
+            named(&mut self.wildcard, '*', name, route)
+        } else if segment.contains(REGEX_SYNTAX) {
+            let pattern = format!("^(?:{})$", segment);
+            let i = match self.regexes.iter().position(|(r, _)| r.as_str() == pattern) {
+                Some(i) => i,
+                None => {
+                    let regex = Regex::new(&pattern).unwrap_or_else(|e| {
+                        panic!("route {:?} has an invalid segment: {}", route, e)
+                    });
+                    self.regexes.push((regex, Node::default()));
+                    self.regexes.len() - 1
+                }
+            };
+            &mut self.regexes[i].1
+        } else {
+            self.statics.entry(segment.to_owned()).or_default()
+        }
+    }
+
+    fn find<'a>(
+        &'a self,
+        segments: &[String],
+        params: &mut Vec<(&'a str, String)>,
+    ) -> Option<&'a Node> {
+        if let Some((segment, rest)) = segments.split_first() {
+            if let Some(node) = self.statics.get(segment) {
+                if let Some(found) = node.find(rest, params) {
+                    return Some(found);
+                }
+            }
+            if let Some((name, node)) = &self.param {
+                params.push((name, segment.clone()));
+                if let Some(found) = node.find(rest, params) {
+                    return Some(found);
+                }
+                params.pop();
+            }
+            let found = self
+                .regexes
+                .iter()
+                .filter(|(regex, _)| regex.is_match(segment))
+                .find_map(|(_, node)| node.find(rest, params));
+            if found.is_some() {
+                return found;
+            }
+        } else if !self.endpoints.is_empty() {
+            return Some(self);
+        }
+
+        let (name, node) = self.wildcard.as_ref()?;
+        params.push((name, segments.join("/")));
+        Some(node)
+    }
+}
+
+/// The characters that make a route segment a regex rather than static text.
+const REGEX_SYNTAX: [char; 13] = [
+    '\\', '^', '$', '|', '?', '*', '+', '(', ')', '[', ']', '{', '}',
+];
+
+/// The child for a `:param` or `*wildcard` segment, panicking if another route gave it a
+/// different name.
+fn named<'a>(
+    slot: &'a mut Option<(String, Box<Node>)>,
+    prefix: char,
+    name: &str,
+    route: &Route,
+) -> &'a mut Node {
+    let (existing, node) = slot.get_or_insert_with(|| (name.to_owned(), Box::default()));
+    if existing != name {
+        panic!(
+            "route {:?} names parameter `{}{}` where another route names it `{}{}`",
+            route, prefix, name, prefix, existing
+        );
+    }
+    node
+}
+
+/// The table of every registered endpoint, built once before the server starts listening. A
+/// lookup descends one level per path segment and finds static segments by hash, but it tries
+/// the regexes at each level in turn and backtracks out of params and regexes whose subtree
+/// doesn't match, so routes that lean on them can cost more than the path length.
+#[derive(Default)]
+pub(crate) struct Router {
+    root: Node,
+}
+
+impl Router {
+    /// Registers an endpoint, panicking if `method` is already handled at `route` or if the route
+    /// is otherwise ambiguous with one registered before it.
+    pub(crate) fn add(
+        &mut self,
+        method: Method,
+        route: Route,
+        handler: Box<dyn Handler>,
+        logger: Option<Arc<Logger>>,
+    ) {
+        let mut node = &mut self.root;
+        for segment in &route.segments {
+            node = node.child(segment, &route);
+        }
+
+        if let Some(existing) = node.endpoints.iter().find(|e| e.method == method) {
+            panic!(
+                "{:?} {:?} conflicts with {:?} {:?}",
+                method, route, existing.method, existing.route
+            );
+        }
+        node.endpoints.push(Endpoint {
+            method,
+            route,
+            handler,
+            logger,
+        });
+    }
+
+    /// Looks up the endpoint handling `method` at `route`, along with the params it captures. HEAD
+    /// falls back to the GET endpoint when it isn't routed itself.
+    pub(crate) fn find(&self, method: &Method, route: &Route) -> Match<'_> {
+        let mut params = Vec::new();
+        let node = match self.root.find(&route.segments, &mut params) {
+            Some(node) => node,
+            None => return Match::NotFound,
+        };
+        let endpoint = |method| node.endpoints.iter().find(|e| &e.method == method);
+        let endpoint = match method {
+            Method::HEAD => endpoint(&Method::HEAD).or_else(|| endpoint(&Method::GET)),
+            _ => endpoint(method),
+        };
+        match endpoint {
+            Some(endpoint) => {
+                let params = params.into_iter().map(|(k, v)| (k.to_owned(), v)).collect();
+                Match::Found(endpoint, params)
+            }
+            None => Match::MethodNotAllowed(node.allowed()),
+        }
+    }
+}
+
+pub(crate) enum Match<'a> {
+    Found(&'a Endpoint, HashMap<String, String>),
+
+    /// The path is routed but not for the requested method. Holds the methods it does allow.
+    MethodNotAllowed(Vec<Method>),
+
+    NotFound,
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::app::Method;
-
     use super::*;
+    use crate::{handler::IntoHandler, Response};
+
+    fn router(routes: &[(Method, &str)]) -> Router {
+        let mut router = Router::default();
+        for (method, route) in routes {
+            router.add(
+                method.clone(),
+                Route::from(route),
+                (|res: Response| async { res }).into_handler(),
+                None,
+            );
+        }
+        router
+    }
+
+    fn found(router: &Router, method: Method, path: &str) -> Option<Route> {
+        match router.find(&method, &Route::from(path)) {
+            Match::Found(e, _) => Some(e.route.clone()),
+            _ => None,
+        }
+    }
 
     #[test]
     fn matches_normal() {
-        let a = Route::from("/");
-        let c = Route::from("/hi/");
-        let d = Route::from("/hi");
+        let router = router(&[(Method::GET, "/"), (Method::GET, "/hi/")]);
 
-        assert_eq!(a, a);
-        assert_ne!(a, c);
-        assert_eq!(c, d);
+        assert_eq!(found(&router, Method::GET, "/"), Some(Route::from("/")));
+        assert_eq!(found(&router, Method::GET, "/hi"), Some(Route::from("/hi")));
+        assert_eq!(found(&router, Method::GET, "/hi/there"), None);
+        assert_eq!(found(&router, Method::POST, "/hi"), None);
+        assert_eq!(
+            found(&router, Method::HEAD, "/hi"),
+            Some(Route::from("/hi"))
+        );
     }
 
     #[test]
     fn matches_regex() {
-        let wild = Route::from("/h+");
-        let hi = Route::from("/hi/");
-        let i = Route::from("/i");
+        let router = router(&[(Method::GET, "/h+"), (Method::GET, "/v[0-9]")]);
 
-        assert_eq!(wild, hi);
-        assert_ne!(wild, i);
+        assert_eq!(
+            found(&router, Method::GET, "/hh/"),
+            Some(Route::from("/h+"))
+        );
+        assert_eq!(found(&router, Method::GET, "/hi"), None);
+        assert_eq!(found(&router, Method::GET, "/i"), None);
+        assert_eq!(
+            found(&router, Method::GET, "/v1"),
+            Some(Route::from("/v[0-9]"))
+        );
+        assert_eq!(found(&router, Method::GET, "/v10"), None);
+        assert_eq!(found(&router, Method::GET, "/api-v1"), None);
+
+        // What an unanchored `/h+` used to match, and segments that used to be regexes
+        let old = self::router(&[(Method::GET, "/.*h+.*"), (Method::GET, "/user_id")]);
+        assert_eq!(
+            found(&old, Method::GET, "/hi/"),
+            Some(Route::from("/.*h+.*"))
+        );
+        assert_eq!(
+            found(&old, Method::GET, "/user_id"),
+            Some(Route::from("/user_id"))
+        );
+        assert_eq!(found(&old, Method::GET, "/userXid"), None);
     }
 
     #[test]
     fn params() {
-        let endpoint = Route::from("/:hi");
-        let mut request = Request {
-            method: Method::GET,
-            route: Route::from("/1"),
-            params: Default::default(),
-            headers: Default::default(),
-            cookies: Default::default(),
-            body: Default::default(),
+        let router = router(&[(Method::GET, "/:hi/:there")]);
+        let params = match router.find(&Method::GET, &Route::from("/1/2")) {
+            Match::Found(_, params) => params,
+            _ => panic!("route didn't match"),
         };
-        request.populate_params(&endpoint);
 
         let mut expected = HashMap::new();
         expected.insert(String::from("hi"), String::from("1"));
+        expected.insert(String::from("there"), String::from("2"));
+
+        assert_eq!(expected, params);
+    }
+
+    #[test]
+    fn method_not_allowed() {
+        let router = router(&[
+            (Method::GET, "/a"),
+            (Method::DELETE, "/a"),
+            (Method::POST, "/b"),
+        ]);
+
+        match router.find(&Method::PUT, &Route::from("/a")) {
+            Match::MethodNotAllowed(allowed) => assert_eq!(
+                allowed,
+                vec![Method::GET, Method::DELETE, Method::HEAD, Method::OPTIONS]
+            ),
+            _ => panic!("expected method not allowed"),
+        }
+        assert!(matches!(
+            router.find(&Method::PUT, &Route::from("/c")),
+            Match::NotFound
+        ));
+    }
+
+    #[test]
+    fn precedence() {
+        let router = router(&[
+            (Method::GET, "/user/[a-z]+"),
+            (Method::GET, "/user/:name"),
+            (Method::GET, "/user/me"),
+            (Method::GET, "/user/:name/posts"),
+        ]);
+
+        let user = |path| found(&router, Method::GET, path).unwrap();
+        assert_eq!(user("/user/me"), Route::from("/user/me"));
+        assert_eq!(user("/user/you"), Route::from("/user/:name"));
+        assert_eq!(user("/user/me/posts"), Route::from("/user/:name/posts"));
+    }
+
+    #[test]
+    fn punctuated_statics() {
+        let router = router(&[
+            (Method::GET, "/about-us"),
+            (Method::GET, "/robots.txt"),
+            (Method::GET, "/:slug"),
+        ]);
+
+        let page = |path| found(&router, Method::GET, path).unwrap();
+        assert_eq!(page("/about-us"), Route::from("/about-us"));
+        assert_eq!(page("/robots.txt"), Route::from("/robots.txt"));
+        assert_eq!(page("/robotsXtxt"), Route::from("/:slug"));
+        assert_eq!(page("/my-robots.txt.bak"), Route::from("/:slug"));
+        assert_eq!(page("/about"), Route::from("/:slug"));
+    }
+
+    #[test]
+    fn wildcard() {
+        let router = router(&[
+            (Method::GET, "/assets/*path"),
+            (Method::GET, "/assets/app.js"),
+            (Method::GET, "/*rest"),
+        ]);
+
+        let found = |path| match router.find(&Method::GET, &Route::from(path)) {
+            Match::Found(e, params) => Some((e.route.clone(), params)),
+            _ => None,
+        };
+        let (route, params) = found("/assets/css/site.css").unwrap();
+        assert_eq!(route, Route::from("/assets/*path"));
+        assert_eq!(params["path"], "css/site.css");
+
+        let (route, params) = found("/assets").unwrap();
+        assert_eq!(route, Route::from("/assets/*path"));
+        assert_eq!(params["path"], "");
+
+        let (route, _) = found("/assets/app.js").unwrap();
+        assert_eq!(route, Route::from("/assets/app.js"));
+
+        let (route, params) = found("/other/page").unwrap();
+        assert_eq!(route, Route::from("/*rest"));
+        assert_eq!(params["rest"], "other/page");
+    }
 
-        assert_eq!(expected, request.params);
+    #[test]
+    #[should_panic(expected = "has a wildcard before its last segment")]
+    fn wildcard_not_last() {
+        router(&[(Method::GET, "/*path/edit")]);
+    }
+
+    #[test]
+    fn decode() {
+        let segments = |path| Route::decode(path).map(|r| r.segments);
+
+        assert_eq!(
+            segments("/user/John%20Doe/"),
+            Some(vec!["user".to_string(), "John Doe".to_string()])
+        );
+        assert_eq!(
+            segments("/files/a%2Fb"),
+            Some(vec!["files".to_string(), "a/b".to_string()])
+        );
+        assert_eq!(
+            segments("/a/./b/../c"),
+            Some(vec!["a".to_string(), "c".to_string()])
+        );
+        assert_eq!(segments("/../%2e%2E/a"), Some(vec!["a".to_string()]));
+        assert_eq!(segments("/a/%zz"), None);
+        assert_eq!(segments("/a/%C3"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "GET /a conflicts with GET /a")]
+    fn duplicate() {
+        router(&[
+            (Method::GET, "/a"),
+            (Method::POST, "/a"),
+            (Method::GET, "/a/"),
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "names parameter `:name` where another route names it `:id`")]
+    fn ambiguous_params() {
+        router(&[(Method::GET, "/user/:id"), (Method::POST, "/user/:name")]);
     }
 }
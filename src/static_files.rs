@@ -0,0 +1,251 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use tokio::fs;
+
+use crate::{
+    handler::{BoxFuture, Handler},
+    route::Route,
+    Request, Response, StatusCode,
+};
+
+/// Serves the files under a directory to requests below a route, as registered by
+/// [`App::serve_dir`](crate::app::App::serve_dir). Paths that would lead outside the directory,
+/// whether through `..`, encoded separators or symlinks, are refused with 403 Forbidden, hidden
+/// files such as `.env` or `.git` are treated as missing, and directories are answered with their
+/// `index.html`, after a redirect to add the trailing slash if the request left it off. Files are
+/// streamed from disk rather than read into memory, and nothing here blocks the runtime.
+pub struct ServeDir {
+    root: PathBuf,
+
+    /// How many segments of the request path name the route rather than a file.
+    prefix: usize,
+}
+
+impl ServeDir {
+    pub fn new(route: impl ToString, root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            prefix: Route::from(route).segments.len(),
+        }
+    }
+}
+
+/// Finds the file `segments` names under `root`, failing with the status to answer with. A
+/// directory whose path doesn't end in a slash is answered with a redirect to one that does,
+/// since relative links in its index would otherwise resolve against its parent.
+async fn resolve(
+    root: &Path,
+    segments: &[String],
+    trailing_slash: bool,
+) -> Result<PathBuf, StatusCode> {
+    let mut path = root.to_path_buf();
+    for segment in segments {
+        // Dot segments, even encoded ones, are resolved before routing, so only an encoded
+        // separator or NUL could still lead somewhere else
+        if segment.contains(['/', '\\', '\0']) {
+            return Err(StatusCode::Forbidden);
+        }
+        if segment.starts_with('.') {
+            return Err(StatusCode::NotFound);
+        }
+        path.push(segment);
+    }
+
+    let root = fs::canonicalize(root)
+        .await
+        .map_err(|_| StatusCode::NotFound)?;
+    let mut path = contained(&root, &path).await?;
+    if fs::metadata(&path).await.is_ok_and(|m| m.is_dir()) {
+        if !trailing_slash {
+            return Err(StatusCode::MovedPermanently);
+        }
+        path = contained(&root, &path.join("index.html")).await?;
+    }
+    match fs::metadata(&path).await {
+        Ok(metadata) if metadata.is_file() => Ok(path),
+        _ => Err(StatusCode::NotFound),
+    }
+}
+
+/// Resolves symlinks in `path`, refusing it if it then lies outside `root`.
+async fn contained(root: &Path, path: &Path) -> Result<PathBuf, StatusCode> {
+    let path = fs::canonicalize(path).await.map_err(|e| match e.kind() {
+        io::ErrorKind::PermissionDenied => StatusCode::Forbidden,
+        _ => StatusCode::NotFound,
+    })?;
+    match path.starts_with(root) {
+        true => Ok(path),
+        false => Err(StatusCode::Forbidden),
+    }
+}
+
+impl Handler for ServeDir {
+    fn call(&self, req: Request, res: Response) -> BoxFuture<Response> {
+        let root = self.root.clone();
+        let prefix = self.prefix;
+        Box::pin(async move {
+            let segments = req.route.segments.get(prefix..).unwrap_or_default();
+            let (path, _) = req.target.split_once('?').unwrap_or((&req.target, ""));
+            match resolve(&root, segments, path.ends_with('/')).await {
+                Ok(path) => res.stream_file(path).await,
+                Err(StatusCode::MovedPermanently) => res
+                    .status(StatusCode::MovedPermanently)
+                    .header("Location", with_trailing_slash(&req.target)),
+                Err(status) => res.status(status).content(status.to_string()),
+            }
+        })
+    }
+}
+
+/// `target` with a slash added to the end of its path. Leading slashes are collapsed, since
+/// `//host/` would send the client to another host.
+fn with_trailing_slash(target: &str) -> String {
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, format!("?{}", query)),
+        None => (target, String::new()),
+    };
+    format!("/{}/{}", path.trim_start_matches('/'), query)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// A directory of files to serve, next to a secret that shouldn't be.
+    fn fixture(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("web-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("public/docs")).unwrap();
+        fs::create_dir_all(dir.join("public/empty")).unwrap();
+        fs::write(dir.join("public/index.html"), "home").unwrap();
+        fs::write(dir.join("public/a b.txt"), "spaced").unwrap();
+        fs::write(dir.join("public/docs/index.html"), "docs").unwrap();
+        fs::write(dir.join("public/.env"), "KEY=secret").unwrap();
+        fs::create_dir_all(dir.join("public/.git")).unwrap();
+        fs::write(dir.join("public/.git/config"), "secret").unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+        dir
+    }
+
+    /// The status-line, without the version, and body that `serve` answers `path` with.
+    async fn get(serve: &ServeDir, path: &str) -> (String, Vec<u8>) {
+        let req = Request::try_from(format!("GET {} HTTP/1.1", path)).unwrap();
+        let mut out = Vec::new();
+        let res = serve.call(req, Response::new()).await;
+        res.write_to(&mut out, false).await.unwrap();
+
+        let end = out.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let status = String::from_utf8(out[9..end].to_vec()).unwrap();
+        let status = status.lines().next().unwrap().to_string();
+        (status, out[end + 4..].to_vec())
+    }
+
+    fn ok(body: &str) -> (String, Vec<u8>) {
+        (String::from("200 OK"), body.as_bytes().to_vec())
+    }
+
+    #[tokio::test]
+    async fn serves_files() {
+        let dir = fixture("serves");
+        let serve = ServeDir::new("/assets", dir.join("public"));
+
+        assert_eq!(get(&serve, "/assets/a%20b.txt").await, ok("spaced"));
+        assert_eq!(get(&serve, "/assets/").await, ok("home"));
+        assert_eq!(get(&serve, "/assets/docs/").await, ok("docs"));
+        assert_eq!(get(&serve, "/assets/docs/?v=1").await, ok("docs"));
+        assert_eq!(get(&serve, "/assets/missing").await.0, "404 Not Found");
+        assert_eq!(get(&serve, "/assets/empty/").await.0, "404 Not Found");
+        assert_eq!(
+            get(&serve, "/assets/missing").await,
+            (String::from("404 Not Found"), b"Not Found".to_vec())
+        );
+
+        // Larger than a chunk, and sent with its length rather than chunked
+        let big: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(dir.join("public/big.bin"), &big).unwrap();
+        assert_eq!(
+            get(&serve, "/assets/big.bin").await,
+            (String::from("200 OK"), big)
+        );
+        let req = Request::try_from(String::from("GET /assets/big.bin HTTP/1.1")).unwrap();
+        let res = serve.call(req, Response::new()).await;
+        let mut out = Vec::new();
+        res.write_to(&mut out, true).await.unwrap();
+        let head = String::from_utf8_lossy(&out[..200]);
+        assert!(head.contains("\r\nContent-Length: 200000\r\n"));
+        assert!(!head.contains("Transfer-Encoding"));
+
+        let serve = ServeDir::new("/", dir.join("missing"));
+        assert_eq!(get(&serve, "/a").await.0, "404 Not Found");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn redirects_directories() {
+        let dir = fixture("redirects");
+        let serve = ServeDir::new("/assets", dir.join("public"));
+        let location = |path: &str| {
+            let req = Request::try_from(format!("GET {} HTTP/1.1", path)).unwrap();
+            let res = serve.call(req, Response::new());
+            async move { res.await.headers().get("Location").map(str::to_string) }
+        };
+
+        assert_eq!(get(&serve, "/assets").await.0, "301 Moved Permanently");
+        assert_eq!(location("/assets").await.as_deref(), Some("/assets/"));
+        assert_eq!(
+            location("/assets/docs?v=1").await.as_deref(),
+            Some("/assets/docs/?v=1")
+        );
+        assert_eq!(
+            location("//assets/docs").await.as_deref(),
+            Some("/assets/docs/")
+        );
+        assert_eq!(location("/assets/docs/").await, None);
+        assert_eq!(location("/assets/a%20b.txt").await, None);
+        assert_eq!(
+            get(&serve, "/assets/empty").await.0,
+            "301 Moved Permanently"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_escapes() {
+        let dir = fixture("escapes");
+        let serve = ServeDir::new("/assets", dir.join("public"));
+
+        assert_eq!(
+            get(&serve, "/assets/..%2Fsecret.txt").await.0,
+            "403 Forbidden"
+        );
+        assert_eq!(
+            get(&serve, "/assets/docs%2F..%2F..%2Fsecret.txt").await.0,
+            "403 Forbidden"
+        );
+        assert_eq!(
+            get(&serve, "/assets/..%5Csecret.txt").await.0,
+            "403 Forbidden"
+        );
+
+        assert_eq!(get(&serve, "/assets/.env").await.0, "404 Not Found");
+        assert_eq!(get(&serve, "/assets/.git/config").await.0, "404 Not Found");
+        assert_eq!(get(&serve, "/assets/%2Eenv").await.0, "404 Not Found");
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("secret.txt"), dir.join("public/link")).unwrap();
+            std::os::unix::fs::symlink(&dir, dir.join("public/docs/up")).unwrap();
+            assert_eq!(get(&serve, "/assets/link").await.0, "403 Forbidden");
+            assert_eq!(
+                get(&serve, "/assets/docs/up/secret.txt").await.0,
+                "403 Forbidden"
+            );
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}